@@ -0,0 +1,169 @@
+//! Compares ways of sorting two-set sweep events by (chr, pos, end before start):
+//!
+//! - `three stable passes`: one radsort pass per field, as before the packed key;
+//! - `packed key`: one radsort call on `sorts::packed_event_key`, on the events;
+//! - `struct of arrays`: the event fields in separate columns, sorted by sorting
+//!   (key, row) pairs and then gathering every column.
+//!
+//! Each timing includes one pass that reads every field of every event in
+//! sorted order, as the sweeps do.
+//!
+//! Run with `cargo bench --bench event_sort -- [n_intervals]`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use radsort::sort_by_key;
+use ruranges::ruranges_structs::GenericEvent;
+use ruranges::sorts::packed_event_key;
+
+const CHROMOSOMES: u64 = 25;
+const CHROMOSOME_LENGTH: u64 = 250_000_000;
+const ROUNDS: usize = 5;
+
+/// The events of `n` random intervals in each of two sets, unsorted.
+fn random_events(n: usize) -> Vec<GenericEvent<i64>> {
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let mut next = move |bound: u64| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state % bound
+    };
+
+    let mut events = Vec::with_capacity(4 * n);
+    for first_set in [true, false] {
+        for idx in 0..n as u32 {
+            let chr = next(CHROMOSOMES) as u32;
+            let start = next(CHROMOSOME_LENGTH) as i64;
+            let end = start + 1 + next(10_000) as i64;
+            for (pos, is_start) in [(start, true), (end, false)] {
+                events.push(GenericEvent {
+                    chr,
+                    pos,
+                    is_start,
+                    first_set,
+                    idx,
+                });
+            }
+        }
+    }
+    events
+}
+
+/// A stand-in for a sweep: reads every field of every event in order.
+fn sweep(chr: &[u32], pos: &[i64], is_start: &[bool], first_set: &[bool], idx: &[u32]) -> u64 {
+    let mut acc = 0u64;
+    for i in 0..chr.len() {
+        acc = acc
+            .wrapping_mul(31)
+            .wrapping_add(chr[i] as u64 ^ pos[i] as u64 ^ idx[i] as u64)
+            .wrapping_add((is_start[i] as u64) << 1 | first_set[i] as u64);
+    }
+    acc
+}
+
+fn sweep_events(events: &[GenericEvent<i64>]) -> u64 {
+    let mut acc = 0u64;
+    for e in events {
+        acc = acc
+            .wrapping_mul(31)
+            .wrapping_add(e.chr as u64 ^ e.pos as u64 ^ e.idx as u64)
+            .wrapping_add((e.is_start as u64) << 1 | e.first_set as u64);
+    }
+    acc
+}
+
+/// The events in both layouts, so that each variant starts from its own.
+struct Input {
+    events: Vec<GenericEvent<i64>>,
+    chr: Vec<u32>,
+    pos: Vec<i64>,
+    is_start: Vec<bool>,
+    first_set: Vec<bool>,
+    idx: Vec<u32>,
+}
+
+fn copy_events(input: &Input) -> Vec<GenericEvent<i64>> {
+    input.events.iter().map(|e| GenericEvent { ..*e }).collect()
+}
+
+fn three_passes(input: &Input) -> u64 {
+    let mut events = copy_events(input);
+    sort_by_key(&mut events, |e| e.is_start);
+    sort_by_key(&mut events, |e| e.pos);
+    sort_by_key(&mut events, |e| e.chr);
+    sweep_events(&events)
+}
+
+fn packed_key(input: &Input) -> u64 {
+    let mut events = copy_events(input);
+    sort_by_key(&mut events, |e| {
+        packed_event_key(e.chr as u64, e.pos, e.is_start)
+    });
+    sweep_events(&events)
+}
+
+fn struct_of_arrays(input: &Input) -> u64 {
+    let (chr, pos, is_start) = (&input.chr, &input.pos, &input.is_start);
+    let mut order: Vec<(u128, u32)> = (0..chr.len())
+        .map(|i| {
+            (
+                packed_event_key(chr[i] as u64, pos[i], is_start[i]),
+                i as u32,
+            )
+        })
+        .collect();
+    sort_by_key(&mut order, |&(key, _)| key);
+
+    fn gather<T: Copy>(order: &[(u128, u32)], column: &[T]) -> Vec<T> {
+        order.iter().map(|&(_, i)| column[i as usize]).collect()
+    }
+    sweep(
+        &gather(&order, chr),
+        &gather(&order, pos),
+        &gather(&order, is_start),
+        &gather(&order, &input.first_set),
+        &gather(&order, &input.idx),
+    )
+}
+
+fn main() {
+    let n: usize = std::env::args()
+        .skip(1)
+        .find(|arg| !arg.starts_with('-'))
+        .map(|arg| arg.parse().expect("n_intervals must be a number"))
+        .unwrap_or(2_000_000);
+    let events = random_events(n);
+    println!("{} events from 2 x {} intervals", events.len(), n);
+    let input = Input {
+        chr: events.iter().map(|e| e.chr).collect(),
+        pos: events.iter().map(|e| e.pos).collect(),
+        is_start: events.iter().map(|e| e.is_start).collect(),
+        first_set: events.iter().map(|e| e.first_set).collect(),
+        idx: events.iter().map(|e| e.idx).collect(),
+        events,
+    };
+
+    let variants: [(&str, fn(&Input) -> u64); 3] = [
+        ("three stable passes", three_passes),
+        ("packed key", packed_key),
+        ("struct of arrays", struct_of_arrays),
+    ];
+    let mut checksums = Vec::new();
+    for (name, variant) in variants {
+        let mut best = Duration::MAX;
+        let mut checksum = 0;
+        for _ in 0..ROUNDS {
+            let started = Instant::now();
+            checksum = black_box(variant(black_box(&input)));
+            best = best.min(started.elapsed());
+        }
+        println!("{:<20} {:>10.1?} (best of {})", name, best, ROUNDS);
+        checksums.push(checksum);
+    }
+    assert!(
+        checksums.windows(2).all(|w| w[0] == w[1]),
+        "the variants sorted differently"
+    );
+}
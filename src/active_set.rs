@@ -0,0 +1,115 @@
+const VACANT: u32 = u32::MAX;
+
+/// The intervals overlapping the current sweep position, keyed by interval
+/// index.
+///
+/// Entries live in one dense vector and `slots[idx]` records where interval
+/// `idx` sits in it, so insert and remove are O(1) without hashing, iteration
+/// is a linear scan, and the iteration order only depends on the order of the
+/// inserts and removes (a removed entry is replaced by the last one).
+#[derive(Debug, Clone)]
+pub struct ActiveSet<V = ()> {
+    entries: Vec<(u32, V)>,
+    slots: Vec<u32>,
+}
+
+impl<V> ActiveSet<V> {
+    /// An empty set with slots for interval indices `0..n`; larger indices
+    /// grow the slots on insert.
+    pub fn with_capacity(n: usize) -> Self {
+        ActiveSet {
+            entries: Vec::new(),
+            slots: vec![VACANT; n],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains(&self, idx: u32) -> bool {
+        self.slot(idx).is_some()
+    }
+
+    pub fn get(&self, idx: u32) -> Option<&V> {
+        self.slot(idx).map(|slot| &self.entries[slot].1)
+    }
+
+    /// Adds `idx`, or replaces its value if it is already active.
+    pub fn insert(&mut self, idx: u32, value: V) {
+        if let Some(slot) = self.slot(idx) {
+            self.entries[slot].1 = value;
+            return;
+        }
+        if idx as usize >= self.slots.len() {
+            self.slots.resize(idx as usize + 1, VACANT);
+        }
+        self.slots[idx as usize] = self.entries.len() as u32;
+        self.entries.push((idx, value));
+    }
+
+    pub fn remove(&mut self, idx: u32) -> Option<V> {
+        let slot = self.slot(idx)?;
+        self.slots[idx as usize] = VACANT;
+        let (_, value) = self.entries.swap_remove(slot);
+        if let Some(&(moved, _)) = self.entries.get(slot) {
+            self.slots[moved as usize] = slot as u32;
+        }
+        Some(value)
+    }
+
+    /// Empties the set in time proportional to its length, not its capacity.
+    pub fn clear(&mut self) {
+        for &(idx, _) in self.entries.iter() {
+            self.slots[idx as usize] = VACANT;
+        }
+        self.entries.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &V)> {
+        self.entries.iter().map(|(idx, value)| (*idx, value))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (u32, &mut V)> {
+        self.entries.iter_mut().map(|(idx, value)| (*idx, value))
+    }
+
+    /// The active interval indices.
+    pub fn idxs(&self) -> impl Iterator<Item = u32> + '_ {
+        self.entries.iter().map(|&(idx, _)| idx)
+    }
+
+    #[inline]
+    fn slot(&self, idx: u32) -> Option<usize> {
+        match self.slots.get(idx as usize) {
+            Some(&slot) if slot != VACANT => Some(slot as usize),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remove_keeps_slots_consistent() {
+        let mut active = ActiveSet::with_capacity(2);
+        for idx in [3, 0, 7, 1] {
+            active.insert(idx, idx * 10);
+        }
+        assert_eq!(active.remove(0), Some(0));
+        assert_eq!(active.remove(0), None);
+        assert_eq!(active.idxs().collect::<Vec<_>>(), vec![3, 1, 7]);
+        assert_eq!(active.get(1), Some(&10));
+        assert_eq!(active.remove(7), Some(70));
+        active.clear();
+        assert!(active.is_empty() && !active.contains(3));
+        active.insert(3, 1);
+        assert_eq!(active.iter().collect::<Vec<_>>(), vec![(3, &1)]);
+    }
+}
@@ -0,0 +1,100 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+/// The chromosome, start and end columns of a BED line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BedRecord {
+    pub chr: String,
+    pub start: i64,
+    pub end: i64,
+}
+
+/// Reads `BedRecord`s from tab-separated text one line at a time. Empty lines,
+/// `#` comments and `track`/`browser` lines are skipped, and any columns after
+/// the third are ignored.
+pub struct BedReader<R> {
+    input: R,
+    line: String,
+    line_number: usize,
+}
+
+impl BedReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(BedReader::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> BedReader<R> {
+    pub fn new(input: R) -> Self {
+        BedReader {
+            input,
+            line: String::new(),
+            line_number: 0,
+        }
+    }
+
+    /// The 1-based number of the line the last record was read from.
+    pub fn line_number(&self) -> usize {
+        self.line_number
+    }
+
+    /// The full line the last record was read from, without the line break.
+    pub fn line(&self) -> &str {
+        self.line.trim_end_matches(['\n', '\r'])
+    }
+
+    fn parse_line(&self) -> io::Result<BedRecord> {
+        let mut fields = self.line().split('\t');
+        let (chr, start, end) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(chr), Some(start), Some(end)) => (chr, start, end),
+            _ => {
+                return Err(self.error("expected chromosome, start and end columns"));
+            }
+        };
+        let position = |field: &str| {
+            field
+                .trim()
+                .parse::<i64>()
+                .map_err(|_| self.error(&format!("invalid position {:?}", field)))
+        };
+        Ok(BedRecord {
+            chr: chr.to_string(),
+            start: position(start)?,
+            end: position(end)?,
+        })
+    }
+
+    /// An `InvalidData` error pointing at the current line.
+    pub fn error(&self, msg: &str) -> io::Error {
+        invalid_data(&format!("line {}: {}", self.line_number, msg))
+    }
+}
+
+impl<R: BufRead> Iterator for BedReader<R> {
+    type Item = io::Result<BedRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.line.clear();
+            match self.input.read_line(&mut self.line) {
+                Ok(0) => return None,
+                Ok(_) => self.line_number += 1,
+                Err(e) => return Some(Err(e)),
+            }
+            let line = self.line.trim_end();
+            if line.is_empty()
+                || line.starts_with('#')
+                || line.starts_with("track")
+                || line.starts_with("browser")
+            {
+                continue;
+            }
+            return Some(self.parse_line());
+        }
+    }
+}
+
+pub fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
@@ -0,0 +1,447 @@
+use std::io::{self, BufRead, Write};
+
+use crate::bed::{BedReader, BedRecord};
+use crate::merge::sweep_line_merge;
+use crate::overlaps::{overlap_end, sweep_line_overlaps};
+use crate::subtract::sweep_line_subtract;
+
+/// A reasonable `max_rows` for the functions below: about 16 MiB of
+/// coordinates per window and file.
+pub const DEFAULT_WINDOW_ROWS: usize = 1 << 20;
+
+/// Writes `chr start end chr2 start2 end2` for every overlapping pair of
+/// intervals of two coordinate-sorted BED inputs, in the order of `input`
+/// and then of `input2`.
+///
+/// The inputs are read one window at a time: a window is a run of at most
+/// `max_rows` intervals of `input` on a single chromosome. Besides the window
+/// only the intervals of `input2` that can still overlap it are kept, so
+/// memory does not depend on the size of the files, only on how many
+/// intervals of `input2` overlap one window. Empty intervals overlap the
+/// intervals containing their position, as in `sweep_line_overlaps`.
+///
+/// The inputs must be sorted by chromosome name (as bytes, like `sort -k1,1`)
+/// and then by start; out of order lines are reported as `InvalidData`.
+pub fn overlaps<R, R2, W>(input: R, input2: R2, mut out: W, max_rows: usize) -> io::Result<()>
+where
+    R: BufRead,
+    R2: BufRead,
+    W: Write,
+{
+    let mut windows = Windows::new(BedReader::new(input), max_rows);
+    let mut others = Windows::new(BedReader::new(input2), max_rows);
+    let mut carried = Window::default();
+
+    // Every row is in one window and sees all of input2 it overlaps, so
+    // windows can be cut anywhere
+    while let Some(window) = windows.next_window(|_, _| true)? {
+        others.carry_overlapping(&window, &mut carried)?;
+
+        let chrs = vec![0u32; window.len()];
+        let chrs2 = vec![0u32; carried.len()];
+        let (idx, idx2) = sweep_line_overlaps(
+            &chrs,
+            &window.starts,
+            &window.ends,
+            &chrs2,
+            &carried.starts,
+            &carried.ends,
+            0,
+            0,
+            None,
+        );
+        let mut pairs: Vec<(u32, u32)> = idx.into_iter().zip(idx2).collect();
+        radsort::sort_by_key(&mut pairs, |&pair| pair);
+
+        for (i, j) in pairs {
+            let (i, j) = (i as usize, j as usize);
+            writeln!(
+                out,
+                "{chr}\t{}\t{}\t{chr}\t{}\t{}",
+                window.starts[i],
+                window.ends[i],
+                carried.starts[j],
+                carried.ends[j],
+                chr = window.chr,
+            )?;
+        }
+    }
+    out.flush()
+}
+
+/// Writes `chr start end` for the parts of the intervals of `input` that no
+/// interval of `input2` covers, in the order of `input`, as
+/// `sweep_line_subtract` does. Reads its inputs window by window like
+/// `overlaps`.
+pub fn subtract<R, R2, W>(input: R, input2: R2, mut out: W, max_rows: usize) -> io::Result<()>
+where
+    R: BufRead,
+    R2: BufRead,
+    W: Write,
+{
+    let mut windows = Windows::new(BedReader::new(input), max_rows);
+    let mut others = Windows::new(BedReader::new(input2), max_rows);
+    let mut carried = Window::default();
+
+    while let Some(window) = windows.next_window(|_, _| true)? {
+        others.carry_overlapping(&window, &mut carried)?;
+
+        let chrs = vec![0i64; window.len()];
+        let chrs2 = vec![0i64; carried.len()];
+        let (idxs, starts, ends) = sweep_line_subtract(
+            &chrs,
+            &window.starts,
+            &window.ends,
+            &chrs2,
+            &carried.starts,
+            &carried.ends,
+        );
+        let mut rows: Vec<usize> = (0..idxs.len()).collect();
+        radsort::sort_by_key(&mut rows, |&r| (idxs[r], starts[r]));
+
+        for r in rows {
+            writeln!(out, "{}\t{}\t{}", window.chr, starts[r], ends[r])?;
+        }
+    }
+    out.flush()
+}
+
+/// Writes `chr start end count` for every run of intervals of a
+/// coordinate-sorted BED input that overlap or lie within `slack` of each
+/// other, reading the input window by window. Runs must not span two
+/// windows, so a window is only cut after `max_rows` intervals where the
+/// next one starts at least `slack` past all of them; without such a gap
+/// the window grows to the whole run.
+pub fn merge<R, W>(input: R, mut out: W, slack: i64, max_rows: usize) -> io::Result<()>
+where
+    R: BufRead,
+    W: Write,
+{
+    let mut windows = Windows::new(BedReader::new(input), max_rows);
+
+    while let Some(window) = windows.next_window(|max_end, start| start >= max_end + slack)? {
+        let chrs = vec![0i64; window.len()];
+        let (_, starts, ends, counts) =
+            sweep_line_merge(&chrs, &window.starts, &window.ends, slack);
+
+        for i in 0..starts.len() {
+            writeln!(
+                out,
+                "{}\t{}\t{}\t{}",
+                window.chr, starts[i], ends[i], counts[i]
+            )?;
+        }
+    }
+    out.flush()
+}
+
+/// Writes `chr start end depth` for every maximal run of positions covered
+/// by the same, non-zero number of intervals of a coordinate-sorted BED
+/// input (the bedGraph of its coverage), reading it window by window like
+/// `merge`: a window grows past `max_rows` until the next uncovered position.
+pub fn coverage<R, W>(input: R, mut out: W, max_rows: usize) -> io::Result<()>
+where
+    R: BufRead,
+    W: Write,
+{
+    let mut windows = Windows::new(BedReader::new(input), max_rows);
+
+    // Windows are only cut at uncovered positions, so runs never span two
+    while let Some(window) = windows.next_window(|max_end, start| start > max_end)? {
+        for (start, end, depth) in depth_runs(&window.starts, &window.ends) {
+            writeln!(out, "{}\t{}\t{}\t{}", window.chr, start, end, depth)?;
+        }
+    }
+    out.flush()
+}
+
+/// (start, end, depth) of the maximal runs of non-zero depth, for starts
+/// sorted ascending.
+fn depth_runs(starts: &[i64], ends: &[i64]) -> Vec<(i64, i64, i64)> {
+    let mut ends = ends.to_vec();
+    radsort::sort(&mut ends);
+
+    let mut runs: Vec<(i64, i64, i64)> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    let mut depth = 0;
+    let mut previous = i64::MIN;
+    while j < ends.len() {
+        let pos = match starts.get(i) {
+            Some(&start) if start < ends[j] => start,
+            _ => ends[j],
+        };
+        if depth > 0 && previous < pos {
+            match runs.last_mut() {
+                Some(run) if run.1 == previous && run.2 == depth => run.1 = pos,
+                _ => runs.push((previous, pos, depth)),
+            }
+        }
+        previous = pos;
+
+        while j < ends.len() && ends[j] == pos {
+            depth -= 1;
+            j += 1;
+        }
+        while i < starts.len() && starts[i] == pos {
+            depth += 1;
+            i += 1;
+        }
+    }
+    runs
+}
+
+/// Intervals of one chromosome, the unit the functions above hold in memory.
+#[derive(Debug, Default)]
+struct Window {
+    chr: String,
+    starts: Vec<i64>,
+    ends: Vec<i64>,
+    // Largest `overlap_end` of the intervals
+    max_end: i64,
+}
+
+impl Window {
+    fn len(&self) -> usize {
+        self.starts.len()
+    }
+
+    fn push(&mut self, record: BedRecord) {
+        if self.starts.is_empty() {
+            self.chr = record.chr;
+            self.max_end = overlap_end(record.start, record.end);
+        }
+        self.max_end = self.max_end.max(overlap_end(record.start, record.end));
+        self.starts.push(record.start);
+        self.ends.push(record.end);
+    }
+}
+
+/// Cuts a coordinate-sorted BED stream into `Window`s and checks the order of
+/// its lines on the way.
+struct Windows<R> {
+    reader: BedReader<R>,
+    peeked: Option<BedRecord>,
+    max_rows: usize,
+    // Chromosome and start of the last record taken, for the order check
+    last_chr: Option<String>,
+    last_start: i64,
+}
+
+impl<R: BufRead> Windows<R> {
+    fn new(reader: BedReader<R>, max_rows: usize) -> Self {
+        Windows {
+            reader,
+            peeked: None,
+            max_rows: max_rows.max(1),
+            last_chr: None,
+            last_start: i64::MIN,
+        }
+    }
+
+    /// The next record, without consuming it.
+    fn peek(&mut self) -> io::Result<Option<&BedRecord>> {
+        if self.peeked.is_none() {
+            self.peeked = self.reader.next().transpose()?;
+        }
+        Ok(self.peeked.as_ref())
+    }
+
+    /// Consumes the peeked record.
+    fn take(&mut self) -> io::Result<BedRecord> {
+        let record = self.peeked.take().unwrap();
+        let in_order = match &self.last_chr {
+            Some(chr) if *chr == record.chr => record.start >= self.last_start,
+            Some(chr) => record.chr > *chr,
+            None => true,
+        };
+        if !in_order {
+            return Err(self
+                .reader
+                .error("input is not sorted by chromosome and start"));
+        }
+        if self.last_chr.as_ref() != Some(&record.chr) {
+            self.last_chr = Some(record.chr.clone());
+        }
+        self.last_start = record.start;
+        Ok(record)
+    }
+
+    /// The intervals up to the next chromosome change, or, once there are
+    /// `max_rows` of them, up to the first record for which
+    /// `can_cut(max_end, start)` holds.
+    fn next_window(&mut self, can_cut: impl Fn(i64, i64) -> bool) -> io::Result<Option<Window>> {
+        let mut window = Window::default();
+        let max_rows = self.max_rows;
+        while let Some(record) = self.peek()? {
+            if !window.starts.is_empty()
+                && (record.chr != window.chr
+                    || (window.len() >= max_rows && can_cut(window.max_end, record.start)))
+            {
+                break;
+            }
+            window.push(self.take()?);
+        }
+        Ok(if window.starts.is_empty() {
+            None
+        } else {
+            Some(window)
+        })
+    }
+
+    /// Updates `carried` to the intervals of this stream that can overlap
+    /// `window`: those on its chromosome that start before its largest
+    /// `overlap_end` and whose `overlap_end` is after its first start. Intervals reaching past the window stay in
+    /// `carried` for the next one.
+    fn carry_overlapping(&mut self, window: &Window, carried: &mut Window) -> io::Result<()> {
+        let lo = window.starts[0];
+        if carried.chr != window.chr {
+            carried.starts.clear();
+            carried.ends.clear();
+            carried.chr = window.chr.clone();
+        } else {
+            let mut kept = 0;
+            for i in 0..carried.len() {
+                if overlap_end(carried.starts[i], carried.ends[i]) > lo {
+                    carried.starts[kept] = carried.starts[i];
+                    carried.ends[kept] = carried.ends[i];
+                    kept += 1;
+                }
+            }
+            carried.starts.truncate(kept);
+            carried.ends.truncate(kept);
+        }
+
+        while let Some(record) = self.peek()? {
+            if record.chr.as_str() > window.chr.as_str()
+                || (record.chr == window.chr && record.start >= window.max_end)
+            {
+                break;
+            }
+            let record = self.take()?;
+            if record.chr == window.chr && overlap_end(record.start, record.end) > lo {
+                carried.starts.push(record.start);
+                carried.ends.push(record.end);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{random_intervals, Rng};
+
+    /// Random coordinate-sorted intervals, with many empty ones, and their BED.
+    fn sorted_bed(rng: &mut Rng, n: usize) -> (Vec<u32>, Vec<i64>, Vec<i64>, String) {
+        let (chrs, starts, ends) = random_intervals(rng, n, 2, 60, 6);
+        let mut rows: Vec<usize> = (0..n).collect();
+        rows.sort_unstable_by_key(|&r| (chrs[r], starts[r]));
+        let chrs: Vec<u32> = rows.iter().map(|&r| chrs[r]).collect();
+        let starts: Vec<i64> = rows.iter().map(|&r| starts[r]).collect();
+        let ends: Vec<i64> = rows.iter().map(|&r| ends[r]).collect();
+        let bed = (0..n)
+            .map(|r| format!("chr{}\t{}\t{}\n", chrs[r], starts[r], ends[r]))
+            .collect();
+        (chrs, starts, ends, bed)
+    }
+
+    #[test]
+    fn test_windows_match_the_in_memory_sweeps() {
+        let mut rng = Rng::new(45);
+        for _ in 0..10 {
+            let (chrs, starts, ends, bed) = sorted_bed(&mut rng, 40);
+            let (chrs2, starts2, ends2, bed2) = sorted_bed(&mut rng, 40);
+
+            let (idx, idx2) =
+                sweep_line_overlaps(&chrs, &starts, &ends, &chrs2, &starts2, &ends2, 0, 0, None);
+            let mut pairs: Vec<(u32, u32)> = idx.into_iter().zip(idx2).collect();
+            pairs.sort_unstable();
+            let expected_overlaps: String = pairs
+                .into_iter()
+                .map(|(i, j)| {
+                    let (i, j) = (i as usize, j as usize);
+                    format!(
+                        "chr{}\t{}\t{}\tchr{}\t{}\t{}\n",
+                        chrs[i], starts[i], ends[i], chrs2[j], starts2[j], ends2[j]
+                    )
+                })
+                .collect();
+
+            let chrs: Vec<i64> = chrs.iter().map(|&c| c as i64).collect();
+            let chrs2: Vec<i64> = chrs2.iter().map(|&c| c as i64).collect();
+            let (idxs, sub_starts, sub_ends) =
+                sweep_line_subtract(&chrs, &starts, &ends, &chrs2, &starts2, &ends2);
+            let mut rows: Vec<usize> = (0..idxs.len()).collect();
+            rows.sort_unstable_by_key(|&r| (idxs[r], sub_starts[r]));
+            let expected_subtract: String = rows
+                .into_iter()
+                .map(|r| format!("chr{}\t{}\t{}\n", chrs[idxs[r]], sub_starts[r], sub_ends[r]))
+                .collect();
+
+            for max_rows in [1, 2, 3, 7, DEFAULT_WINDOW_ROWS] {
+                let mut out = Vec::new();
+                overlaps(bed.as_bytes(), bed2.as_bytes(), &mut out, max_rows).unwrap();
+                assert_eq!(
+                    String::from_utf8(out).unwrap(),
+                    expected_overlaps,
+                    "{}",
+                    max_rows
+                );
+
+                let mut out = Vec::new();
+                subtract(bed.as_bytes(), bed2.as_bytes(), &mut out, max_rows).unwrap();
+                assert_eq!(
+                    String::from_utf8(out).unwrap(),
+                    expected_subtract,
+                    "{}",
+                    max_rows
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_windows_give_the_same_output() {
+        let bed = "chr1\t0\t10\nchr1\t5\t15\nchr1\t20\t30\nchr2\t0\t5\n";
+        let bed2 = "chr1\t8\t22\nchr1\t25\t26\nchr2\t1\t2\n";
+
+        for max_rows in [1, DEFAULT_WINDOW_ROWS] {
+            let mut out = Vec::new();
+            overlaps(bed.as_bytes(), bed2.as_bytes(), &mut out, max_rows).unwrap();
+            assert_eq!(
+                String::from_utf8(out).unwrap(),
+                "chr1\t0\t10\tchr1\t8\t22\n\
+                 chr1\t5\t15\tchr1\t8\t22\n\
+                 chr1\t20\t30\tchr1\t8\t22\n\
+                 chr1\t20\t30\tchr1\t25\t26\n\
+                 chr2\t0\t5\tchr2\t1\t2\n"
+            );
+
+            let mut out = Vec::new();
+            subtract(bed.as_bytes(), bed2.as_bytes(), &mut out, max_rows).unwrap();
+            assert_eq!(
+                String::from_utf8(out).unwrap(),
+                "chr1\t0\t8\nchr1\t5\t8\nchr1\t22\t25\nchr1\t26\t30\nchr2\t0\t1\nchr2\t2\t5\n"
+            );
+
+            let mut out = Vec::new();
+            merge(bed.as_bytes(), &mut out, 0, max_rows).unwrap();
+            assert_eq!(
+                String::from_utf8(out).unwrap(),
+                "chr1\t0\t15\t2\nchr1\t20\t30\t1\nchr2\t0\t5\t1\n"
+            );
+
+            let mut out = Vec::new();
+            coverage(bed.as_bytes(), &mut out, max_rows).unwrap();
+            assert_eq!(
+                String::from_utf8(out).unwrap(),
+                "chr1\t0\t5\t1\nchr1\t5\t10\t2\nchr1\t10\t15\t1\nchr1\t20\t30\t1\nchr2\t0\t5\t1\n"
+            );
+        }
+
+        let unsorted = "chr1\t5\t10\nchr1\t0\t10\n";
+        let err = merge(unsorted.as_bytes(), io::sink(), 0, 1).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -6,11 +6,29 @@ pub fn sweep_line_cluster(
     ends: &[i64],
     slack: i64,
 ) -> (Vec<i64>, Vec<usize>) {
-    let mut indices = Vec::with_capacity(chrs.len());
-    let mut cluster_ids = Vec::with_capacity(chrs.len());
+    let mut cluster_ids = vec![0; chrs.len()];
+    let mut indices = vec![0; chrs.len()];
+    sweep_line_cluster_into(chrs, starts, ends, slack, &mut cluster_ids, &mut indices);
+    (cluster_ids, indices)
+}
+
+/// `sweep_line_cluster` writing into `cluster_ids` and `indices`, which must
+/// both have one slot per interval.
+pub fn sweep_line_cluster_into(
+    chrs: &[i64],
+    starts: &[i64],
+    ends: &[i64],
+    slack: i64,
+    cluster_ids: &mut [i64],
+    indices: &mut [usize],
+) {
+    assert!(
+        cluster_ids.len() == chrs.len() && indices.len() == chrs.len(),
+        "outputs must have one slot per interval"
+    );
 
     if chrs.is_empty() {
-        return (cluster_ids, indices);
+        return;
     };
 
     let events = sorts::build_sorted_events_single_collection(chrs, starts, ends, slack);
@@ -18,6 +36,7 @@ pub fn sweep_line_cluster(
     let mut current_chr: i64 = events.first().unwrap().chr;
     let mut current_cluster: i64 = 0;
     let mut active_intervals: i64 = 0;
+    let mut row = 0;
 
     for e in events {
         if e.chr != current_chr {
@@ -27,8 +46,9 @@ pub fn sweep_line_cluster(
         }
 
         if e.is_start {
-            indices.push(e.idx);
-            cluster_ids.push(current_cluster);
+            indices[row] = e.idx;
+            cluster_ids[row] = current_cluster;
+            row += 1;
             active_intervals += 1;
         } else {
             active_intervals -= 1;
@@ -37,6 +57,4 @@ pub fn sweep_line_cluster(
             }
         }
     }
-
-    (cluster_ids, indices)
 }
@@ -1,6 +1,6 @@
-use crate::sorts;
-
-use rustc_hash::FxHashSet;
+use crate::active_set::ActiveSet;
+use crate::overlaps::{self, FourWayMerge, WhichList};
+use crate::sorts::{self, build_sorted_events_single_collection_separate_outputs};
 
 pub fn sweep_line_non_overlaps(
     chrs: &[i64],
@@ -19,19 +19,25 @@ pub fn sweep_line_non_overlaps(
         return no_overlaps.to_vec();
     }
 
-    // Build up the event list in ascending order (same as before)
-    let events = sorts::build_sorted_events_idxs(chrs, starts, ends, chrs2, starts2, ends2, slack);
+    // Build up the four sorted event lists; only set 1 is extended by the slack
+    let (chrs, chrs2) = sorts::chromosome_codes(chrs, chrs2);
+    let (sorted_starts, sorted_ends) =
+        overlaps::compute_sorted_events(&chrs, starts, ends, slack, slack, None, false);
+    let sorted_starts2 = build_sorted_events_single_collection_separate_outputs(&chrs2, starts2, 0);
+    let sorted_ends2 = build_sorted_events_single_collection_separate_outputs(&chrs2, ends2, 0);
 
-    let mut overlapped = FxHashSet::default();
+    let mut overlapped = vec![false; chrs.len()];
 
     // Active sets
-    let mut active1 = FxHashSet::default();
-    let mut active2 = FxHashSet::default();
+    let mut active1 = ActiveSet::with_capacity(chrs.len());
+    let mut active2 = ActiveSet::with_capacity(chrs2.len());
 
-    // Assume the first event determines the “current” chr
-    let mut current_chr: i64 = events.first().unwrap().chr;
+    // The smallest chromosome is the “current” one to begin with
+    let mut current_chr = sorted_starts[0].chr.min(sorted_starts2[0].chr);
 
-    for e in events {
+    for (which_list, e) in
+        FourWayMerge::new(&sorted_starts, &sorted_ends, &sorted_starts2, &sorted_ends2)
+    {
         // If chromosome changed, clear active sets
         if e.chr != current_chr {
             active1.clear();
@@ -39,37 +45,70 @@ pub fn sweep_line_non_overlaps(
             current_chr = e.chr;
         }
 
-        if e.is_start {
+        let idx = e.idx as usize;
+        match which_list {
             // Interval is starting
-            if e.first_set {
+            WhichList::StartSet1 => {
                 // Overlaps with all currently active intervals in set2
                 if !active2.is_empty() {
-                    overlapped.insert(e.idx);
+                    overlapped[idx] = true;
                 }
                 // Insert into active1
-                active1.insert(e.idx);
-            } else {
+                active1.insert(e.idx, ());
+            }
+            WhichList::StartSet2 => {
                 // Overlaps with all currently active intervals in set1
-                for &idx1 in active1.iter() {
-                    overlapped.insert(idx1);
+                for idx1 in active1.idxs() {
+                    overlapped[idx1 as usize] = true;
                 }
                 // Insert into active2
-                active2.insert(e.idx);
+                active2.insert(e.idx, ());
             }
-        } else {
             // Interval is ending
-            if e.first_set {
-                active1.remove(&e.idx);
-                if !overlapped.contains(&e.idx) {
-                    no_overlaps.push(e.idx);
+            WhichList::EndSet1 => {
+                active1.remove(e.idx);
+                if !overlapped[idx] {
+                    no_overlaps.push(idx);
                 }
-            } else {
-                active2.remove(&e.idx);
             }
-
-            overlapped.remove(&e.idx);
+            WhichList::EndSet2 => {
+                active2.remove(e.idx);
+            }
         }
     }
 
     no_overlaps
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{random_intervals, Rng};
+
+    #[test]
+    fn test_non_overlaps_match_the_event_sweep() {
+        let mut rng = Rng::new(37);
+        for slack in [0, 3] {
+            let (chrs, starts, ends) = random_intervals(&mut rng, 60, 3, 200, 25);
+            let (chrs2, starts2, ends2) = random_intervals(&mut rng, 60, 3, 200, 25);
+            // sweep_line_non_overlaps sweeps the raw ends, which keeps empty
+            // intervals open, so compare on non-empty ones
+            let ends: Vec<i64> = ends.iter().map(|e| e + 1).collect();
+            let ends2: Vec<i64> = ends2.iter().map(|e| e + 1).collect();
+
+            let (overlapping, _) = overlaps::sweep_line_overlaps(
+                &chrs, &starts, &ends, &chrs2, &starts2, &ends2, slack, slack, None,
+            );
+            let expected: Vec<usize> = (0..chrs.len())
+                .filter(|&i| !overlapping.contains(&(i as u32)))
+                .collect();
+
+            let chrs: Vec<i64> = chrs.iter().map(|&c| c as i64).collect();
+            let chrs2: Vec<i64> = chrs2.iter().map(|&c| c as i64).collect();
+            let mut found =
+                sweep_line_non_overlaps(&chrs, &starts, &ends, &chrs2, &starts2, &ends2, slack);
+            found.sort_unstable();
+            assert_eq!(found, expected, "slack={}", slack);
+        }
+    }
+}
@@ -0,0 +1,107 @@
+/// The number of bins of `bin_size` positions along chromosomes of the given
+/// lengths, the last bin of a chromosome being cut short at its end.
+pub fn bin_count(chrom_lengths: &[i64], bin_size: i64) -> Result<usize, &'static str> {
+    if bin_size <= 0 {
+        return Err("bin_size must be positive");
+    }
+    Ok(chrom_lengths
+        .iter()
+        .map(|&length| chrom_bins(length, bin_size))
+        .sum())
+}
+
+fn chrom_bins(length: i64, bin_size: i64) -> usize {
+    ((length.max(0) + bin_size - 1) / bin_size) as usize
+}
+
+/// Writes, for every bin of `bin_size` positions along every chromosome, how
+/// many positions in it the intervals cover, counting a position once per
+/// interval covering it. The bins of chromosome code 0 come first, then those
+/// of code 1 and so on; `coverage` must hold `bin_count` of them. Parts of an
+/// interval outside `[0, chrom_lengths[chr])` are left out.
+pub fn coverage_per_bin_into(
+    chrs: &[u32],
+    starts: &[i64],
+    ends: &[i64],
+    chrom_lengths: &[i64],
+    bin_size: i64,
+    coverage: &mut [i64],
+) -> Result<(), &'static str> {
+    assert_eq!(
+        coverage.len(),
+        bin_count(chrom_lengths, bin_size)?,
+        "coverage must have one slot per bin"
+    );
+    let mut offsets = Vec::with_capacity(chrom_lengths.len());
+    let mut offset = 0;
+    for &length in chrom_lengths {
+        offsets.push(offset);
+        offset += chrom_bins(length, bin_size);
+    }
+
+    coverage.fill(0);
+    for ((&chr, &start), &end) in chrs.iter().zip(starts).zip(ends) {
+        let Some(&length) = chrom_lengths.get(chr as usize) else {
+            return Err("chromosome code without a length in chrom_lengths");
+        };
+        let (start, end) = (start.max(0), end.min(length));
+        if start >= end {
+            continue;
+        }
+        let mut bin_start = start - start % bin_size;
+        while bin_start < end {
+            let covered = end.min(bin_start + bin_size) - start.max(bin_start);
+            coverage[offsets[chr as usize] + (bin_start / bin_size) as usize] += covered;
+            bin_start += bin_size;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{random_intervals, Rng};
+
+    #[test]
+    fn test_coverage_per_bin_matches_brute_force() {
+        let mut rng = Rng::new(49);
+        let chrom_lengths = [120, 0, 77];
+        for bin_size in [1, 7, 50, 200] {
+            let (chrs, starts, ends) = random_intervals(&mut rng, 40, 3, 130, 30);
+
+            let mut expected = Vec::new();
+            for (chr, &length) in chrom_lengths.iter().enumerate() {
+                for bin_start in (0..length).step_by(bin_size as usize) {
+                    let bin_end = (bin_start + bin_size).min(length);
+                    let covered = (bin_start..bin_end)
+                        .map(|p| {
+                            (0..chrs.len())
+                                .filter(|&i| {
+                                    chrs[i] as usize == chr && starts[i] <= p && p < ends[i]
+                                })
+                                .count() as i64
+                        })
+                        .sum::<i64>();
+                    expected.push(covered);
+                }
+            }
+
+            let mut coverage = vec![-1; bin_count(&chrom_lengths, bin_size).unwrap()];
+            coverage_per_bin_into(
+                &chrs,
+                &starts,
+                &ends,
+                &chrom_lengths,
+                bin_size,
+                &mut coverage,
+            )
+            .unwrap();
+            assert_eq!(coverage, expected, "bin_size={}", bin_size);
+        }
+
+        assert!(bin_count(&chrom_lengths, 0).is_err());
+        let mut coverage = vec![0; 2];
+        assert!(coverage_per_bin_into(&[2], &[0], &[5], &[10, 0], 5, &mut coverage).is_err());
+    }
+}
@@ -0,0 +1,246 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::bed::{BedReader, BedRecord};
+
+/// A reasonable `max_rows` for `sort_bed`.
+pub const DEFAULT_RUN_ROWS: usize = 1 << 22;
+
+/// Most runs merged in one pass. More are first merged into fewer, longer
+/// runs, so the number of open files stays bounded.
+const MAX_MERGE_WIDTH: usize = 128;
+
+/// Sorts the lines of a BED input by chromosome name (as bytes, like
+/// `sort -k1,1`), then start, then end, keeping lines that compare equal in
+/// input order. This is the order the `chunked` functions expect.
+///
+/// At most `max_rows` lines are held in memory. A larger input is cut into
+/// sorted runs of that many lines, which are written to temporary files in
+/// `tmp_dir` and then merged k ways. The temporary files are removed again,
+/// also when sorting fails. Lines are written back unchanged, but comment,
+/// `track` and `browser` lines are dropped.
+pub fn sort_bed<R, W>(input: R, out: W, max_rows: usize, tmp_dir: &Path) -> io::Result<()>
+where
+    R: BufRead,
+    W: Write,
+{
+    sort_bed_merging(input, out, max_rows, tmp_dir, MAX_MERGE_WIDTH)
+}
+
+/// `sort_bed` merging at most `width` runs at a time.
+fn sort_bed_merging<R, W>(
+    input: R,
+    mut out: W,
+    max_rows: usize,
+    tmp_dir: &Path,
+    width: usize,
+) -> io::Result<()>
+where
+    R: BufRead,
+    W: Write,
+{
+    let max_rows = max_rows.max(1);
+    let mut reader = BedReader::new(input);
+    let mut runs = Runs::new(tmp_dir);
+
+    let mut lines: Vec<(BedRecord, String)> = Vec::new();
+    while let Some(record) = reader.next() {
+        lines.push((record?, reader.line().to_string()));
+        if lines.len() == max_rows {
+            sort_run(&mut lines);
+            runs.spill(&lines)?;
+            lines.clear();
+        }
+    }
+    sort_run(&mut lines);
+
+    if runs.paths.is_empty() {
+        // Everything fit in memory
+        for (_, line) in &lines {
+            writeln!(out, "{}", line)?;
+        }
+        return out.flush();
+    }
+    if !lines.is_empty() {
+        runs.spill(&lines)?;
+    }
+    runs.merge_into(out, width.max(2))
+}
+
+fn sort_run(lines: &mut [(BedRecord, String)]) {
+    lines.sort_by(|(a, _), (b, _)| (&a.chr, a.start, a.end).cmp(&(&b.chr, b.start, b.end)));
+}
+
+/// The sorted runs written so far, in input order. They are deleted when
+/// the value is dropped.
+struct Runs {
+    dir: PathBuf,
+    paths: Vec<PathBuf>,
+}
+
+impl Runs {
+    fn new(dir: &Path) -> Self {
+        Runs {
+            dir: dir.to_path_buf(),
+            paths: Vec::new(),
+        }
+    }
+
+    /// Creates a file for a new run. The counter keeps concurrent sorts apart,
+    /// and a name that is already taken, e.g. by a file planted in a shared
+    /// temporary directory, is skipped rather than opened.
+    fn create(&mut self) -> io::Result<(PathBuf, File)> {
+        static NEXT_RUN: AtomicUsize = AtomicUsize::new(0);
+        loop {
+            let path = self.dir.join(format!(
+                "ruranges-sort-{}-{}.bed",
+                std::process::id(),
+                NEXT_RUN.fetch_add(1, Ordering::Relaxed)
+            ));
+            match File::options().write(true).create_new(true).open(&path) {
+                Ok(file) => {
+                    self.paths.push(path.clone());
+                    return Ok((path, file));
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn spill(&mut self, lines: &[(BedRecord, String)]) -> io::Result<()> {
+        let mut file = BufWriter::new(self.create()?.1);
+        for (_, line) in lines {
+            writeln!(file, "{}", line)?;
+        }
+        file.flush()
+    }
+
+    /// Merges the runs into `out`, at most `width` at a time.
+    fn merge_into<W: Write>(mut self, out: W, width: usize) -> io::Result<()> {
+        while self.paths.len() > width {
+            // Merge neighbouring runs so that ties keep their input order
+            let level = self.paths.clone();
+            let mut next_level = Vec::new();
+            for batch in level.chunks(width) {
+                let (path, file) = self.create()?;
+                merge_runs(batch, BufWriter::new(file))?;
+                next_level.push(path);
+                for run in batch {
+                    fs::remove_file(run)?;
+                }
+                self.paths.retain(|p| !batch.contains(p));
+            }
+            self.paths = next_level;
+        }
+        merge_runs(&self.paths, out)
+    }
+}
+
+impl Drop for Runs {
+    fn drop(&mut self) {
+        for path in &self.paths {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// K-way merge of sorted run files. Ties go to the earlier run.
+fn merge_runs<W: Write>(paths: &[PathBuf], mut out: W) -> io::Result<()> {
+    let mut readers = paths
+        .iter()
+        .map(|path| Ok(BedReader::new(BufReader::new(File::open(path)?))))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    // The heap holds the sort key of the next line of every run, and `heads`
+    // the line itself
+    let mut heap = BinaryHeap::with_capacity(readers.len());
+    let mut heads = vec![String::new(); readers.len()];
+    for (run, reader) in readers.iter_mut().enumerate() {
+        push_next(&mut heap, &mut heads, reader, run)?;
+    }
+    while let Some(Reverse((_, _, _, run))) = heap.pop() {
+        writeln!(out, "{}", heads[run])?;
+        push_next(&mut heap, &mut heads, &mut readers[run], run)?;
+    }
+    out.flush()
+}
+
+fn push_next<R: BufRead>(
+    heap: &mut BinaryHeap<Reverse<(String, i64, i64, usize)>>,
+    heads: &mut [String],
+    reader: &mut BedReader<R>,
+    run: usize,
+) -> io::Result<()> {
+    if let Some(record) = reader.next().transpose()? {
+        heads[run].clear();
+        heads[run].push_str(reader.line());
+        heap.push(Reverse((record.chr, record.start, record.end, run)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spilled_runs_merge_in_order() {
+        let bed = "chr2\t5\t9\ta\nchr1\t7\t8\tb\nchr10\t0\t1\tc\nchr1\t7\t8\td\nchr1\t2\t3\te\n";
+        let expected =
+            "chr1\t2\t3\te\nchr1\t7\t8\tb\nchr1\t7\t8\td\nchr10\t0\t1\tc\nchr2\t5\t9\ta\n";
+
+        for max_rows in [1, 2, DEFAULT_RUN_ROWS] {
+            let mut out = Vec::new();
+            sort_bed(bed.as_bytes(), &mut out, max_rows, &std::env::temp_dir()).unwrap();
+            assert_eq!(String::from_utf8(out).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_narrow_merges_take_several_passes() {
+        // Few distinct keys, so that the tag column shows whether ties stay
+        // in input order across the passes
+        let mut rng = crate::test_util::Rng::new(46);
+        let rows: Vec<(String, i64, i64, usize)> = (0..60)
+            .map(|tag| {
+                let start = rng.below(4) as i64;
+                let chr = format!("chr{}", rng.below(3));
+                (chr, start, start + 1 + rng.below(2) as i64, tag)
+            })
+            .collect();
+        let line = |(chr, start, end, tag): &(String, i64, i64, usize)| {
+            format!("{}\t{}\t{}\t{}\n", chr, start, end, tag)
+        };
+        let bed: String = rows.iter().map(line).collect();
+        let mut sorted = rows.clone();
+        sorted.sort_by(|a, b| (&a.0, a.1, a.2).cmp(&(&b.0, b.1, b.2)));
+        let expected: String = sorted.iter().map(line).collect();
+
+        let tmp_dir =
+            std::env::temp_dir().join(format!("ruranges-merge-test-{}", std::process::id()));
+        fs::create_dir_all(&tmp_dir).unwrap();
+        // 60, 30 and 20 runs merged 2 or 3 at a time
+        for (max_rows, width) in [(1, 2), (2, 3), (3, 2), (1, 1)] {
+            let mut out = Vec::new();
+            sort_bed_merging(bed.as_bytes(), &mut out, max_rows, &tmp_dir, width).unwrap();
+            assert_eq!(
+                String::from_utf8(out).unwrap(),
+                expected,
+                "{} {}",
+                max_rows,
+                width
+            );
+            assert_eq!(
+                fs::read_dir(&tmp_dir).unwrap().count(),
+                0,
+                "runs were left behind"
+            );
+        }
+        fs::remove_dir(&tmp_dir).unwrap();
+    }
+}
@@ -0,0 +1,784 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::marker::PhantomData;
+use std::ops::{Deref, Range};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use memmap2::Mmap;
+
+use crate::{
+    nearest::{
+        nearest_from_sorted_events, Direction, DistanceMetric, NearestOptions, NearestPairs,
+    },
+    overlaps::{self, overlap_end},
+    ruranges_structs::{MinEvent, OverlapPair},
+    sorts::{self, build_sorted_events_single_collection_separate_outputs},
+};
+
+/// Leading bytes of a saved index.
+pub const INDEX_MAGIC: &[u8; 8] = b"RURIDX\0\0";
+/// Bumped whenever the on-disk layout changes; older files are rejected.
+pub const INDEX_FORMAT_VERSION: u32 = 3;
+
+/// A collection of intervals sorted once up front, so that a query is a few
+/// binary searches into it instead of a sweep over all of it.
+///
+/// Per chromosome, the intervals are kept sorted by start together with the
+/// running maximum of their ends (an augmented sorted array). The intervals
+/// overlapping `[s, e)` all start before the first start `>= e`, and walking
+/// back from there can stop once the running maximum end is `<= s`. The ends
+/// are also kept sorted on their own, for counting and for the left nearest
+/// scan. A query costs O(log n) plus its hits, plus the intervals it steps
+/// over that end before it: a single long interval keeps the running maximum
+/// high for every interval that starts after it.
+#[derive(Debug, Clone)]
+pub struct IntervalIndex {
+    /// (chr, start, idx), sorted by chromosome, start and end.
+    sorted_starts: Column<MinEvent>,
+    /// (chr, end, idx), sorted by chromosome and end.
+    sorted_ends: Column<MinEvent>,
+    /// The end of each interval of `sorted_starts`.
+    ends: Column<i64>,
+    /// Running maximum of the `overlap_end`s within each chromosome.
+    max_ends: Column<i64>,
+    /// The first row of each chromosome, in chromosome order.
+    chroms: Column<ChromBlock>,
+}
+
+/// Where the rows of chromosome `chr` start in `sorted_starts` and `sorted_ends`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ChromBlock {
+    chr: u32,
+    first_row: u32,
+}
+
+/// The chromosome blocks of events sorted by chromosome.
+fn chrom_blocks(sorted: &[MinEvent]) -> Vec<ChromBlock> {
+    let mut blocks: Vec<ChromBlock> = Vec::new();
+    for (row, e) in sorted.iter().enumerate() {
+        if blocks.last().is_none_or(|b| b.chr != e.chr) {
+            blocks.push(ChromBlock {
+                chr: e.chr,
+                first_row: row as u32,
+            });
+        }
+    }
+    blocks
+}
+
+impl IntervalIndex {
+    /// Indexes the intervals. The three columns must have the same length, of
+    /// at most `u32::MAX` rows.
+    pub fn new(chrs: &[u32], starts: &[i64], ends: &[i64]) -> Result<Self, &'static str> {
+        if starts.len() != chrs.len() || ends.len() != chrs.len() {
+            return Err("chrs, starts and ends must have the same length");
+        }
+        if chrs.len() > u32::MAX as usize {
+            return Err("An index holds at most u32::MAX intervals");
+        }
+        let mut intervals: Vec<(u32, i64, i64, u32)> = (0..chrs.len())
+            .map(|i| (chrs[i], starts[i], ends[i], i as u32))
+            .collect();
+        sorts::sort_by_chr(&mut intervals, |iv| iv.0, |iv| (iv.1, iv.2));
+
+        let mut max_ends: Vec<i64> = Vec::with_capacity(intervals.len());
+        for (i, &(chr, start, end, _)) in intervals.iter().enumerate() {
+            let end = overlap_end(start, end);
+            max_ends.push(match i.checked_sub(1) {
+                Some(prev) if intervals[prev].0 == chr => max_ends[prev].max(end),
+                _ => end,
+            });
+        }
+
+        let sorted_starts: Vec<MinEvent> = intervals
+            .iter()
+            .map(|&(chr, pos, _, idx)| MinEvent { chr, pos, idx })
+            .collect();
+        Ok(IntervalIndex {
+            chroms: Column::Owned(chrom_blocks(&sorted_starts)),
+            sorted_starts: Column::Owned(sorted_starts),
+            sorted_ends: Column::Owned(build_sorted_events_single_collection_separate_outputs(
+                chrs, ends, 0,
+            )),
+            ends: Column::Owned(intervals.iter().map(|&(_, _, end, _)| end).collect()),
+            max_ends: Column::Owned(max_ends),
+        })
+    }
+
+    /// Number of intervals in the index.
+    pub fn len(&self) -> usize {
+        self.sorted_starts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sorted_starts.is_empty()
+    }
+
+    /// The rows of chromosome `chr`, the same in `sorted_starts` and `sorted_ends`.
+    fn block(&self, chr: u32) -> Range<usize> {
+        let i = self.chroms.partition_point(|b| b.chr < chr);
+        let first_row = |i: usize| {
+            self.chroms
+                .get(i)
+                .map_or(self.len(), |b| b.first_row as usize)
+        };
+        match self.chroms.get(i) {
+            Some(b) if b.chr == chr => first_row(i)..first_row(i + 1),
+            _ => first_row(i)..first_row(i),
+        }
+    }
+
+    /// Pushes the indexed intervals of `block` that start before `before` and
+    /// whose end (from `end_of`) is after `after`, in start order.
+    fn push_hits(
+        &self,
+        block: Range<usize>,
+        before: impl Fn(i64) -> bool,
+        after: i64,
+        end_of: impl Fn(usize) -> i64,
+        query: u32,
+        hits: &mut Vec<OverlapPair>,
+    ) {
+        let first = hits.len();
+        let stop =
+            block.start + self.sorted_starts[block.clone()].partition_point(|e| before(e.pos));
+        for j in (block.start..stop).rev() {
+            if self.max_ends[j] <= after {
+                break;
+            }
+            if end_of(j) > after {
+                hits.push(OverlapPair {
+                    idx: query,
+                    idx2: self.sorted_starts[j].idx,
+                });
+            }
+        }
+        hits[first..].reverse();
+    }
+
+    /// All (query idx, index idx) pairs that overlap, by query and then by
+    /// start. The queries are extended by `slack` on both sides.
+    pub fn overlaps(
+        &self,
+        chrs: &[u32],
+        starts: &[i64],
+        ends: &[i64],
+        slack: i64,
+    ) -> Vec<OverlapPair> {
+        let mut hits = Vec::new();
+        for i in 0..chrs.len() {
+            let (start, end) = sorts::extend_interval(starts[i], ends[i], slack, slack);
+            let end = overlap_end(start, end);
+            self.push_hits(
+                self.block(chrs[i]),
+                |pos| pos < end,
+                start,
+                |j| overlap_end(self.sorted_starts[j].pos, self.ends[j]),
+                i as u32,
+                &mut hits,
+            );
+        }
+        hits
+    }
+
+    /// Number of indexed intervals overlapping each query, in query order.
+    /// Uses binary searches only, so it does not depend on the number of hits.
+    pub fn count(&self, chrs: &[u32], starts: &[i64], ends: &[i64], slack: i64) -> Vec<u32> {
+        let mut counts = vec![0; chrs.len()];
+        self.count_into(chrs, starts, ends, slack, &mut counts);
+        counts
+    }
+
+    /// `count` writing into `counts`, which must have one slot per query.
+    pub fn count_into(
+        &self,
+        chrs: &[u32],
+        starts: &[i64],
+        ends: &[i64],
+        slack: i64,
+        counts: &mut [u32],
+    ) {
+        assert_eq!(
+            counts.len(),
+            chrs.len(),
+            "counts must have one slot per query"
+        );
+        for i in 0..chrs.len() {
+            let (start, end) = sorts::extend_interval(starts[i], ends[i], slack, slack);
+            let end = overlap_end(start, end);
+            let block = self.block(chrs[i]);
+            let by_start = &self.sorted_starts[block.clone()];
+
+            // (intervals starting before the end) - (intervals ending by the start)
+            let started = by_start.partition_point(|e| e.pos < end);
+            let ended = self.sorted_ends[block.clone()].partition_point(|e| e.pos <= start);
+            // ...except that empty intervals at the start have ended but still
+            // overlap. They come first among the intervals starting there.
+            let at_start = block.start + by_start.partition_point(|e| e.pos < start);
+            let empty_at_start =
+                self.ends[at_start..block.start + started].partition_point(|&e| e <= start);
+
+            counts[i] = (started + empty_at_start)
+                .checked_sub(ended)
+                .expect("an indexed interval ends before it starts") as u32;
+        }
+    }
+
+    /// `nearest::nearest` with the index as the second set. Only the edge
+    /// metrics are supported, since the index does not keep reference points,
+    /// so the strand flags are not used; neither is `threads`.
+    pub fn nearest(
+        &self,
+        chrs: &[u32],
+        starts: &[i64],
+        ends: &[i64],
+        options: &NearestOptions,
+    ) -> Result<NearestPairs, &'static str> {
+        let NearestOptions {
+            slack,
+            k,
+            include_overlaps,
+            direction,
+            max_distance,
+            distance_metric,
+            k_by_intervals,
+            ..
+        } = *options;
+        let dir = Direction::from_str(direction)?;
+        let metric = DistanceMetric::from_str(distance_metric)?;
+        if !metric.is_edge() {
+            return Err("The interval index only supports the edge distance metrics");
+        }
+
+        let (sorted_starts, sorted_ends) =
+            overlaps::compute_sorted_events(chrs, starts, ends, slack, slack, None, false);
+
+        // Already grouped by query
+        let overlaps = if include_overlaps {
+            self.overlaps(chrs, starts, ends, slack)
+        } else {
+            Vec::new()
+        };
+
+        Ok(nearest_from_sorted_events(
+            &sorted_starts,
+            &self.sorted_ends,
+            &sorted_ends,
+            &self.sorted_starts,
+            &overlaps,
+            dir,
+            k,
+            max_distance,
+            metric,
+            k_by_intervals,
+        ))
+    }
+
+    /// All (position idx, index idx) pairs where the indexed interval
+    /// contains the position.
+    pub fn contains_point(&self, chrs: &[u32], positions: &[i64]) -> Vec<OverlapPair> {
+        let mut hits = Vec::new();
+        for (i, &pos) in positions.iter().enumerate() {
+            self.push_hits(
+                self.block(chrs[i]),
+                |start| start <= pos,
+                pos,
+                |j| self.ends[j],
+                i as u32,
+                &mut hits,
+            );
+        }
+        hits
+    }
+
+    /// Writes the index in the versioned binary format read by `load`.
+    ///
+    /// All values are little-endian, and every section starts at a multiple
+    /// of 8 bytes:
+    ///
+    /// ```text
+    /// magic          [u8; 8]
+    /// version        u32
+    /// reserved       u32 (0)
+    /// n_intervals    u64
+    /// sorted_starts  n_intervals x (chr: u32, 0u32, pos: i64, idx: u32, 0u32)
+    /// sorted_ends    n_intervals x (chr: u32, 0u32, pos: i64, idx: u32, 0u32)
+    /// ends           n_intervals x i64
+    /// max_ends       n_intervals x i64
+    /// n_chroms       u64
+    /// chroms         n_chroms x (chr: u32, first_row: u32)
+    /// ```
+    ///
+    /// The file is written under a new temporary name next to `path` and then
+    /// renamed over it, so indexes already loaded from `path` keep reading
+    /// the file they mapped.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        static SAVES: AtomicUsize = AtomicUsize::new(0);
+
+        let path = path.as_ref();
+        let mut tmp_name = path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No file name to save to"))?
+            .to_owned();
+        tmp_name.push(format!(
+            ".{}.{}.tmp",
+            std::process::id(),
+            SAVES.fetch_add(1, Ordering::Relaxed)
+        ));
+        let tmp = path.with_file_name(tmp_name);
+
+        let file = File::options().write(true).create_new(true).open(&tmp)?;
+        let result = self
+            .write_to(BufWriter::new(file))
+            .and_then(|()| fs::rename(&tmp, path));
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        result
+    }
+
+    fn write_to(&self, mut out: impl Write) -> io::Result<()> {
+        out.write_all(INDEX_MAGIC)?;
+        out.write_all(&INDEX_FORMAT_VERSION.to_le_bytes())?;
+        out.write_all(&0u32.to_le_bytes())?;
+        out.write_all(&(self.len() as u64).to_le_bytes())?;
+        for events in [&self.sorted_starts, &self.sorted_ends] {
+            for e in events.iter() {
+                out.write_all(&e.chr.to_le_bytes())?;
+                out.write_all(&0u32.to_le_bytes())?;
+                out.write_all(&e.pos.to_le_bytes())?;
+                out.write_all(&e.idx.to_le_bytes())?;
+                out.write_all(&0u32.to_le_bytes())?;
+            }
+        }
+        for values in [&self.ends, &self.max_ends] {
+            for v in values.iter() {
+                out.write_all(&v.to_le_bytes())?;
+            }
+        }
+        out.write_all(&(self.chroms.len() as u64).to_le_bytes())?;
+        for b in self.chroms.iter() {
+            out.write_all(&b.chr.to_le_bytes())?;
+            out.write_all(&b.first_row.to_le_bytes())?;
+        }
+        out.flush()
+    }
+
+    /// Memory-maps an index written by `save`. The columns are read from the
+    /// map in place, not copied, after a single pass that checks them; a file
+    /// that fails the checks is an `InvalidData` error.
+    ///
+    /// The file must not be modified while the index, or a clone of it, is
+    /// alive. `save` never does so, since it replaces files rather than
+    /// rewriting them, but truncating or writing to the file by other means
+    /// is undefined behaviour.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // Safety: ruranges only replaces index files (see `save`), so the map
+        // does not change under us; other writers are excluded by the
+        // contract above.
+        let map = Arc::new(unsafe { Mmap::map(&file)? });
+        let mut reader = Reader {
+            map: &map,
+            offset: 0,
+        };
+
+        if reader.take(INDEX_MAGIC.len())? != INDEX_MAGIC {
+            return Err(invalid_data("Not a ruranges index file"));
+        }
+        let version = reader.u32()?;
+        if version != INDEX_FORMAT_VERSION {
+            return Err(invalid_data(&format!(
+                "Unsupported index format version {} (expected {})",
+                version, INDEX_FORMAT_VERSION
+            )));
+        }
+        reader.u32()?;
+        let n = usize::try_from(reader.u64()?).map_err(|_| truncated())?;
+
+        let sorted_starts = reader.column(n)?;
+        let sorted_ends = reader.column(n)?;
+        let ends = reader.column(n)?;
+        let max_ends = reader.column(n)?;
+        let n_chroms = usize::try_from(reader.u64()?).map_err(|_| truncated())?;
+        let index = IntervalIndex {
+            sorted_starts,
+            sorted_ends,
+            ends,
+            max_ends,
+            chroms: reader.column(n_chroms)?,
+        };
+        if reader.offset != map.len() {
+            return Err(invalid_data("Index file has trailing bytes"));
+        }
+        index.validate()?;
+        Ok(index)
+    }
+
+    /// Checks the invariants the queries rely on, for an index read from disk.
+    fn validate(&self) -> io::Result<()> {
+        let n = self.len();
+        if n > u32::MAX as usize {
+            return Err(invalid_data("Index has more intervals than u32 idx values"));
+        }
+        if !self
+            .sorted_ends
+            .windows(2)
+            .all(|w| (w[0].chr, w[0].pos) <= (w[1].chr, w[1].pos))
+        {
+            return Err(invalid_data("Index ends are not sorted"));
+        }
+
+        // Every idx below n has exactly one end...
+        let mut end_of: Vec<Option<(u32, i64)>> = vec![None; n];
+        for e in self.sorted_ends.iter() {
+            match end_of.get_mut(e.idx as usize) {
+                Some(slot @ None) => *slot = Some((e.chr, e.pos)),
+                _ => return Err(invalid_data("Index idx values are not a permutation")),
+            }
+        }
+        // ...and exactly one start, on the same chromosome and not after it
+        let mut seen = vec![false; n];
+        let mut max_end = i64::MIN;
+        for (i, e) in self.sorted_starts.iter().enumerate() {
+            match seen.get_mut(e.idx as usize) {
+                Some(seen @ false) => *seen = true,
+                _ => return Err(invalid_data("Index idx values are not a permutation")),
+            }
+            let end = self.ends[i];
+            if end_of[e.idx as usize] != Some((e.chr, end)) || end < e.pos {
+                return Err(invalid_data("Index starts and ends do not match"));
+            }
+
+            let same_chr = i > 0 && self.sorted_starts[i - 1].chr == e.chr;
+            if i > 0 {
+                let prev = &self.sorted_starts[i - 1];
+                if (prev.chr, prev.pos, self.ends[i - 1]) > (e.chr, e.pos, end) {
+                    return Err(invalid_data("Index starts are not sorted"));
+                }
+            }
+            max_end = if same_chr {
+                max_end.max(overlap_end(e.pos, end))
+            } else {
+                overlap_end(e.pos, end)
+            };
+            if self.max_ends[i] != max_end {
+                return Err(invalid_data("Index running maximum ends are wrong"));
+            }
+        }
+        // The ends are on the same chromosomes, so their blocks are the same
+        if *self.chroms != chrom_blocks(&self.sorted_starts)[..] {
+            return Err(invalid_data("Index chromosome offsets are wrong"));
+        }
+        Ok(())
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn truncated() -> io::Error {
+    invalid_data("Index file is truncated")
+}
+
+/// One column of an index: built in memory, or read in place from a mapped
+/// file.
+#[derive(Debug, Clone)]
+enum Column<T> {
+    Owned(Vec<T>),
+    Mapped {
+        map: Arc<Mmap>,
+        offset: usize,
+        len: usize,
+        values: PhantomData<T>,
+    },
+}
+
+impl<T> Deref for Column<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        match self {
+            Column::Owned(values) => values,
+            // Safety: `Reader::column` only maps ranges that lie inside the
+            // map and are aligned for `T`, and `T: Record` accepts any bytes.
+            Column::Mapped {
+                map, offset, len, ..
+            } => unsafe { std::slice::from_raw_parts(map.as_ptr().add(*offset).cast(), *len) },
+        }
+    }
+}
+
+/// A value stored in index files as `WIDTH` little-endian bytes.
+///
+/// # Safety
+///
+/// On little-endian targets, when `size_of::<Self>() == WIDTH`, the stored
+/// bytes must be the in-memory representation of the value, and any bytes
+/// must be a valid value.
+unsafe trait Record: Sized {
+    const WIDTH: usize;
+
+    fn from_le_bytes(bytes: &[u8]) -> Self;
+}
+
+unsafe impl Record for i64 {
+    const WIDTH: usize = 8;
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        i64::from_le_bytes(bytes.try_into().unwrap())
+    }
+}
+
+// `MinEvent` is `repr(C)`: chr, 4 bytes of padding, pos, idx, 4 bytes of padding.
+unsafe impl Record for MinEvent {
+    const WIDTH: usize = 24;
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        MinEvent {
+            chr: u32::from_le_bytes(bytes[0..4].try_into().unwrap()),
+            pos: i64::from_le_bytes(bytes[8..16].try_into().unwrap()),
+            idx: u32::from_le_bytes(bytes[16..20].try_into().unwrap()),
+        }
+    }
+}
+
+// `ChromBlock` is `repr(C)`: chr, first_row.
+unsafe impl Record for ChromBlock {
+    const WIDTH: usize = 8;
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        ChromBlock {
+            chr: u32::from_le_bytes(bytes[0..4].try_into().unwrap()),
+            first_row: u32::from_le_bytes(bytes[4..8].try_into().unwrap()),
+        }
+    }
+}
+
+/// Little-endian cursor over the mapped index file.
+struct Reader<'a> {
+    map: &'a Arc<Mmap>,
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let end = self
+            .offset
+            .checked_add(len)
+            .filter(|&end| end <= self.map.len())
+            .ok_or_else(truncated)?;
+        let bytes = &self.map[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    /// `n` values of `T`, in place when the target's layout of `T` matches
+    /// the file's and decoded otherwise.
+    fn column<T: Record>(&mut self, n: usize) -> io::Result<Column<T>> {
+        let offset = self.offset;
+        let bytes = self.take(n.checked_mul(T::WIDTH).ok_or_else(truncated)?)?;
+        let in_place = cfg!(target_endian = "little")
+            && std::mem::size_of::<T>() == T::WIDTH
+            && (bytes.as_ptr() as usize).is_multiple_of(std::mem::align_of::<T>());
+        Ok(if in_place {
+            Column::Mapped {
+                map: Arc::clone(self.map),
+                offset,
+                len: n,
+                values: PhantomData,
+            }
+        } else {
+            Column::Owned(bytes.chunks_exact(T::WIDTH).map(T::from_le_bytes).collect())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{random_intervals, Rng};
+
+    /// The pairs of `overlaps` found by testing every pair.
+    fn brute_force(
+        (chrs, starts, ends): &(Vec<u32>, Vec<i64>, Vec<i64>),
+        (chrs2, starts2, ends2): &(Vec<u32>, Vec<i64>, Vec<i64>),
+    ) -> Vec<(u32, u32)> {
+        let mut pairs = Vec::new();
+        for i in 0..chrs.len() {
+            for j in 0..chrs2.len() {
+                if chrs[i] == chrs2[j]
+                    && starts2[j] < overlap_end(starts[i], ends[i])
+                    && starts[i] < overlap_end(starts2[j], ends2[j])
+                {
+                    pairs.push((i as u32, j as u32));
+                }
+            }
+        }
+        pairs
+    }
+
+    #[test]
+    fn test_queries_match_brute_force() {
+        let mut rng = Rng::new(32);
+        for _ in 0..50 {
+            let queries = random_intervals(&mut rng, 40, 3, 200, 30);
+            let indexed = random_intervals(&mut rng, 60, 3, 200, 30);
+            let (chrs, starts, ends) = &queries;
+            let index = IntervalIndex::new(&indexed.0, &indexed.1, &indexed.2).unwrap();
+
+            let mut hits: Vec<(u32, u32)> = index
+                .overlaps(chrs, starts, ends, 0)
+                .iter()
+                .map(|p| (p.idx, p.idx2))
+                .collect();
+            hits.sort_unstable();
+            let expected = brute_force(&queries, &indexed);
+            assert_eq!(hits, expected);
+
+            let mut counts = vec![0; chrs.len()];
+            for &(i, _) in &expected {
+                counts[i as usize] += 1;
+            }
+            assert_eq!(index.count(chrs, starts, ends, 0), counts);
+
+            let mut contained: Vec<(u32, u32)> = index
+                .contains_point(chrs, starts)
+                .iter()
+                .map(|p| (p.idx, p.idx2))
+                .collect();
+            contained.sort_unstable();
+            let mut expected = Vec::new();
+            for i in 0..chrs.len() {
+                for j in 0..indexed.0.len() {
+                    if chrs[i] == indexed.0[j]
+                        && indexed.1[j] <= starts[i]
+                        && starts[i] < indexed.2[j]
+                    {
+                        expected.push((i as u32, j as u32));
+                    }
+                }
+            }
+            assert_eq!(contained, expected);
+
+            // The sweep keeps empty intervals open, so compare on non-empty ones
+            let ends: Vec<i64> = ends.iter().map(|e| e + 1).collect();
+            let ends2: Vec<i64> = indexed.2.iter().map(|e| e + 1).collect();
+            let index = IntervalIndex::new(&indexed.0, &indexed.1, &ends2).unwrap();
+            let options = NearestOptions {
+                k: 2,
+                ..NearestOptions::default()
+            };
+            let from_index = index.nearest(chrs, starts, &ends, &options).unwrap();
+            let from_sweep = crate::nearest::nearest(
+                chrs, starts, &ends, &indexed.0, &indexed.1, &ends2, &options,
+            )
+            .unwrap();
+            let sorted = |(idx, idx2, distance): (Vec<u32>, Vec<u32>, Vec<i64>)| {
+                let mut rows: Vec<_> = (0..idx.len())
+                    .map(|r| (idx[r], idx2[r], distance[r]))
+                    .collect();
+                rows.sort_unstable();
+                rows
+            };
+            assert_eq!(sorted(from_index), sorted(from_sweep));
+        }
+    }
+
+    /// A path in the temp dir that no other test, or test run, uses.
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("ruranges_{}_{}.idx", name, std::process::id()))
+    }
+
+    fn query(index: &IntervalIndex) -> Vec<(u32, u32)> {
+        index
+            .overlaps(&[1, 1, 2], &[0, 25, 2], &[12, 35, 6], 0)
+            .iter()
+            .map(|p| (p.idx, p.idx2))
+            .collect()
+    }
+
+    #[test]
+    fn test_save_load_roundtrip() {
+        let index = IntervalIndex::new(&[2, 1, 1, 2], &[5, 30, 10, 0], &[15, 40, 20, 3]).unwrap();
+
+        let path = temp_path("save_load_roundtrip");
+        index.save(&path).unwrap();
+        let loaded = IntervalIndex::load(&path).unwrap();
+
+        for (a, b) in [
+            (&index.sorted_starts, &loaded.sorted_starts),
+            (&index.sorted_ends, &loaded.sorted_ends),
+        ] {
+            assert_eq!(a.len(), b.len());
+            for (x, y) in a.iter().zip(b.iter()) {
+                assert_eq!((x.chr, x.pos, x.idx), (y.chr, y.pos, y.idx));
+            }
+        }
+        assert_eq!(*index.ends, *loaded.ends);
+        assert_eq!(*index.max_ends, *loaded.max_ends);
+        assert_eq!(*index.chroms, *loaded.chroms);
+        assert_eq!(query(&loaded), vec![(0, 2), (1, 1), (2, 3), (2, 0)]);
+
+        // Saving over the file leaves the loaded index reading the old one
+        IntervalIndex::new(&[1], &[0], &[100])
+            .unwrap()
+            .save(&path)
+            .unwrap();
+        assert_eq!(query(&loaded), vec![(0, 2), (1, 1), (2, 3), (2, 0)]);
+        assert_eq!(
+            query(&IntervalIndex::load(&path).unwrap()),
+            vec![(0, 0), (1, 0)]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_new_rejects_mismatched_columns() {
+        assert!(IntervalIndex::new(&[1, 1], &[0], &[5, 6]).is_err());
+        assert!(IntervalIndex::new(&[1], &[0], &[5, 6]).is_err());
+    }
+
+    #[test]
+    fn test_load_rejects_corrupt_files() {
+        let path = temp_path("load_rejects_corrupt_files");
+        IntervalIndex::new(&[2, 1, 1, 2], &[5, 30, 10, 0], &[15, 40, 20, 3])
+            .unwrap()
+            .save(&path)
+            .unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+
+        // The first idx of sorted_starts is at 24 + 16
+        let mut duplicated = bytes.clone();
+        duplicated.copy_within(64..68, 40);
+        let mut out_of_range = bytes.clone();
+        out_of_range[40..44].copy_from_slice(&4u32.to_le_bytes());
+        let mut unsorted = bytes.clone();
+        unsorted[32..40].copy_from_slice(&50i64.to_le_bytes());
+        let truncated = bytes[..bytes.len() - 1].to_vec();
+        // The last chromosome block (chr 2) starts at row 2
+        let mut wrong_offset = bytes.clone();
+        let last_row = bytes.len() - 4;
+        wrong_offset[last_row..].copy_from_slice(&3u32.to_le_bytes());
+
+        for corrupt in [duplicated, out_of_range, unsorted, truncated, wrong_offset] {
+            std::fs::write(&path, corrupt).unwrap();
+            let err = IntervalIndex::load(&path).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,8 +1,15 @@
+pub mod active_set;
+pub mod bed;
 pub mod boundary;
+pub mod chunked;
 pub mod cluster;
 pub mod complement;
 pub mod complement_single;
+pub mod coverage;
+pub mod external_sort;
+pub mod interval_index;
 pub mod merge;
+pub mod multiprocessing;
 pub mod nearest;
 pub mod numpy_bindings;
 pub mod overlaps;
@@ -10,6 +17,9 @@ pub mod ruranges_structs;
 pub mod sorts;
 pub mod spliced_subsequence;
 pub mod split;
+pub mod streaming;
 pub mod subtract;
+#[cfg(test)]
+mod test_util;
 pub mod tile;
 pub mod max_disjoint;
@@ -1,10 +1,11 @@
 use std::hash::Hash;
-use clap::Parser;
-use num_traits::{PrimInt, Signed, Zero};
+use clap::{Parser, Subcommand};
+use num_traits::{PrimInt, Zero};
 use polars::prelude::*;
 use polars::datatypes::DataType;
 
 
+use ruranges::external_sort;
 use ruranges::overlaps;
 use rustc_hash::FxHashMap;
 use std::fs::File;
@@ -12,21 +13,104 @@ use std::io::{self, BufWriter};
 use std::path::PathBuf;
 use std::time::Instant;
 
-/// Simple program to read two CSV files as DataFrames using Polars.
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Args {
-    /// Path to the first CSV file
-    input1: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Path to the first CSV file, to overlap without a subcommand
+    #[arg(required = true)]
+    input1: Option<PathBuf>,
 
     /// Path to the second CSV file
-    input2: PathBuf,
+    #[arg(required = true)]
+    input2: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Read two CSV files as DataFrames using Polars and overlap them.
+    Overlaps {
+        /// Path to the first CSV file
+        input1: PathBuf,
+
+        /// Path to the second CSV file
+        input2: PathBuf,
+    },
+    /// Sort a BED file by chromosome, start and end, spilling sorted runs to
+    /// temporary files when it does not fit in memory.
+    Sort {
+        /// Path to the BED file
+        input: PathBuf,
+
+        /// Where to write the sorted file (default: stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Lines held in memory per sorted run
+        #[arg(long, default_value_t = external_sort::DEFAULT_RUN_ROWS)]
+        max_rows: usize,
+
+        /// Directory for the sorted runs (default: the system temp directory)
+        #[arg(long)]
+        tmp_dir: Option<PathBuf>,
+    },
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Parse command-line arguments
-    let args = Args::parse();
+    run(Args::parse())
+}
+
+fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    let command = match (args.command, args.input1, args.input2) {
+        (Some(command), _, _) => command,
+        // `ruranges input1 input2`, as before the subcommands
+        (None, Some(input1), Some(input2)) => Command::Overlaps { input1, input2 },
+        (None, _, _) => unreachable!("clap requires both inputs without a subcommand"),
+    };
+
+    match command {
+        Command::Overlaps { input1, input2 } => overlap_csv_files(input1, input2),
+        Command::Sort {
+            input,
+            output,
+            max_rows,
+            tmp_dir,
+        } => sort_bed_file(input, output, max_rows, tmp_dir),
+    }
+}
+
+fn sort_bed_file(
+    input: PathBuf,
+    output: Option<PathBuf>,
+    max_rows: usize,
+    tmp_dir: Option<PathBuf>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let input = io::BufReader::new(File::open(input)?);
+    let tmp_dir = tmp_dir.unwrap_or_else(std::env::temp_dir);
+    match output {
+        Some(path) => {
+            let out = BufWriter::new(File::create(path)?);
+            external_sort::sort_bed(input, out, max_rows, &tmp_dir)?
+        }
+        None => {
+            let out = BufWriter::new(io::stdout().lock());
+            external_sort::sort_bed(input, out, max_rows, &tmp_dir)?
+        }
+    }
+    Ok(())
+}
 
+fn overlap_csv_files(file1: PathBuf, file2: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
     let columns = Arc::new(vec![0, 1, 2]);
 
     // Open the first CSV file and create a CsvReader
@@ -41,7 +125,6 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     
     // Build a schema from the fields.
-    let file1 = args.input1;
     let csv_reader = CsvReadOptions::default()
     .with_has_header(false)
     .with_schema_overwrite(Some(std::sync::Arc::new(schema.clone())))
@@ -52,7 +135,6 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     .finish()?;
 
     let parse_options: CsvParseOptions = CsvParseOptions::default().with_separator(b'\t');
-    let file2 = args.input2;
     let csv2 = CsvReadOptions::default()
     .with_has_header(false)
     .with_schema_overwrite(Some(std::sync::Arc::new(schema)))
@@ -78,14 +160,18 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let (c1, c2) = factorize_binary(col1, col2)?;
     let (c1, c2) = process_columns::<UInt32Type>(&csv, &csv2, "column_1".into())?;
     println!("{:?}", start.elapsed());
-    let starts = csv.column("column_2")?.i32()?;
+    let starts = csv.column("column_2")?.cast(&DataType::Int64)?;
+    let starts = starts.i64()?;
     println!("{:?}", start.elapsed());
-    let ends = csv.column("column_3")?.i32()?;
+    let ends = csv.column("column_3")?.cast(&DataType::Int64)?;
+    let ends = ends.i64()?;
     println!("{:?}", start.elapsed());
     println!("{:?}", start.elapsed());
-    let starts2 = csv2.column("column_2")?.i32()?;
+    let starts2 = csv2.column("column_2")?.cast(&DataType::Int64)?;
+    let starts2 = starts2.i64()?;
     println!("{:?}", start.elapsed());
-    let ends2 = csv2.column("column_3")?.i32()?;
+    let ends2 = csv2.column("column_3")?.cast(&DataType::Int64)?;
+    let ends2 = ends2.i64()?;
     println!("{:?}", start.elapsed());
     let (mut idx, _) = overlaps::sweep_line_overlaps(
         c1.cont_slice()?,
@@ -94,7 +180,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         c2.cont_slice()?,
         starts2.cont_slice()?,
         ends2.cont_slice()?,
-        0_i32,
+        0,
+        0,
+        None,
     );
     println!("{:?}", idx.len());
     radsort::sort(&mut idx);
@@ -108,10 +196,6 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     //     .include_header(false)
     //     .with_separator(b'\t')
     //     .finish(&mut res)?;
-    // let lf = LazyCsvReader::new(file1)
-    // .with_has_header(false)
-    // .finish()?;
-    // process_chunks(lf, idx, 1_000_000u32)?;
     
     Ok(())
 }
@@ -124,7 +208,7 @@ fn process_columns<T>(
 where
     T: PolarsNumericType,
     // The *native* integer type must be prim-int-like etc.
-    T::Native: PrimInt + Signed + Hash + Copy + radsort::Key + Zero + TryFrom<usize>, {
+    T::Native: PrimInt + Hash + Copy + radsort::Key + Zero + TryFrom<usize>, {
     // Create a local map that will be dropped when this function ends.
     let mut global_map: FxHashMap<String, T::Native> = FxHashMap::default();
     
@@ -141,7 +225,7 @@ fn encode_strings_to_codes<T>(
 where
     T: PolarsNumericType,
     // Require T::Native to be convertible from usize.
-    T::Native: PrimInt + Signed + Hash + Copy + radsort::Key + Zero + TryFrom<usize>,
+    T::Native: PrimInt + Hash + Copy + radsort::Key + Zero + TryFrom<usize>,
 {
     if s.dtype() != &DataType::String {
         return Err(PolarsError::ComputeError(
@@ -200,58 +284,36 @@ fn factorize_binary(
     Ok((codes1, codes2))
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-fn process_chunks(
-    lf: LazyFrame,
-    sorted_indices: Vec<u32>,
-    chunk_size: u32,
-) -> PolarsResult<()> {
-    use polars::prelude::*;
-    let stdout = std::io::stdout();
-    let mut handle = stdout.lock();
-    let total_rows = lf.clone().collect()?.height() as u32;
-    let mut indices_iter = sorted_indices.into_iter().peekable();
-    let mut chunk_start = 0u32;
-    
-    while chunk_start < total_rows && indices_iter.peek().is_some() {
-        let chunk_end = (chunk_start + chunk_size).min(total_rows);
-        let mut chunk_indices = Vec::new();
-        
-        while let Some(&idx) = indices_iter.peek() {
-            if idx >= chunk_end {
-                break;
-            }
-            chunk_indices.push(indices_iter.next().unwrap());
-        }
-        
-        if chunk_indices.is_empty() {
-            chunk_start = chunk_end;
-            continue;
-        }
-        
-        let partial_df = lf
-            .clone()
-            .slice(chunk_start as i64, (chunk_end - chunk_start) as u32)
-            .collect()?;
-            
-        // Create a Series of local indices that preserves duplicates
-        let local_offsets: Vec<u32> = chunk_indices
-            .iter()
-            .map(|&idx| idx - chunk_start)
-            .collect();
-            
-        // Create a new DataFrame with just the rows we need, potentially duplicated
-        let idx_ca = UInt32Chunked::from_vec("idx".into(), local_offsets);
-
-        let mut subset = partial_df.take(&idx_ca)?;
-        
-        // Write all rows at once
-        CsvWriter::new(&mut handle)
-            .include_header(false)
-            .with_separator(b'\t')
-            .finish(&mut subset)?;
-            
-        chunk_start = chunk_end;
+    #[test]
+    fn test_sort_command_sorts_a_bed_file() {
+        let dir = std::env::temp_dir().join(format!("ruranges-sort-cli-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.bed");
+        let output = dir.join("out.bed");
+        std::fs::write(&input, "chr2\t5\t9\nchr1\t7\t8\nchr1\t3\t4\nchr2\t1\t2\n").unwrap();
+
+        let args = Args::try_parse_from([
+            "ruranges".as_ref(),
+            "sort".as_ref(),
+            input.as_os_str(),
+            "--output".as_ref(),
+            output.as_os_str(),
+            "--max-rows".as_ref(),
+            "1".as_ref(),
+            "--tmp-dir".as_ref(),
+            dir.as_os_str(),
+        ])
+        .unwrap();
+        run(args).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&output).unwrap(),
+            "chr1\t3\t4\nchr1\t7\t8\nchr2\t1\t2\nchr2\t5\t9\n"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
     }
-    Ok(())
-}
\ No newline at end of file
+}
@@ -1,5 +1,9 @@
 use std::fmt;
+use std::sync::{Arc, Mutex};
 
+use rayon::prelude::*;
+
+use crate::overlaps::FourWayMerge;
 use crate::ruranges_structs::MinEvent;
 
 
@@ -40,47 +44,6 @@ impl fmt::Display for ChrBound {
     }
 }
 
-/// Returns boundary indices [0, ..., data.len()] whenever `chr` changes.
-/// E.g. if `data` has chr=1 for indices [0..2), chr=2 for [2..5), etc.,
-/// then you might get [0, 2, 5] (and finally data.len()).
-fn find_chr_boundaries(data: &[MinEvent]) -> Vec<usize> {
-    let mut boundaries = Vec::new();
-    if data.is_empty() {
-        return boundaries;
-    }
-
-    // Always push the start index 0
-    boundaries.push(0);
-
-    // Mark where the chromosome changes
-    for i in 1..data.len() {
-        if data[i].chr != data[i - 1].chr {
-            boundaries.push(i);
-        }
-    }
-
-    // Push the final end
-    boundaries.push(data.len());
-    boundaries
-}
-
-/// Converts boundary indices into a list of (chr, start_index, end_index) blocks.
-/// Each block covers all MinEvents for a single chromosome in `data`.
-fn build_chr_blocks(data: &[MinEvent], boundaries: &[usize]) -> Vec<(i64, usize, usize)> {
-    let mut blocks = Vec::new();
-    for w in boundaries.windows(2) {
-        let start = w[0];
-        let end = w[1];
-        // If start < end, we have a real block
-        if start < end {
-            let chrom = data[start].chr;
-            blocks.push((chrom, start, end));
-        }
-    }
-    blocks
-}
-
-
 /// A small helper struct for the final results.
 /// Each partition covers [start1..end1) in `sorted_starts`
 /// and [start2..end2) in `sorted_starts2`.
@@ -96,7 +59,7 @@ pub struct PartitionIndex {
 /// set of events on a single chromosome.
 #[derive(Debug)]
 struct ChromRange {
-    chr: i64,
+    chr: u32,
     start_idx: usize,
     end_idx: usize, // end_idx is exclusive
 }
@@ -113,8 +76,8 @@ fn group_by_chromosome(events: &[MinEvent]) -> Vec<ChromRange> {
     let mut current_chr = events[0].chr;
     let mut current_start = 0usize;
 
-    for i in 1..events.len() {
-        if events[i].chr != current_chr {
+    for (i, event) in events.iter().enumerate().skip(1) {
+        if event.chr != current_chr {
             // We've hit a new chromosome, close out the old range
             ranges.push(ChromRange {
                 chr: current_chr,
@@ -122,7 +85,7 @@ fn group_by_chromosome(events: &[MinEvent]) -> Vec<ChromRange> {
                 end_idx: i,
             });
             // start a new range
-            current_chr = events[i].chr;
+            current_chr = event.chr;
             current_start = i;
         }
     }
@@ -136,108 +99,337 @@ fn group_by_chromosome(events: &[MinEvent]) -> Vec<ChromRange> {
     ranges
 }
 
-/// Partition a single sorted slice (grouped by chromosome) into N partitions.
-/// Each partition is represented as (start_index, end_index) into the original slice.
-fn partition_chrom_ranges(
-    events: &[MinEvent],
-    num_partitions: usize,
-) -> Vec<(usize, usize)> {
-    if events.is_empty() {
-        // If no events, return num_partitions empty partitions
-        return (0..num_partitions).map(|_| (0, 0)).collect();
+/// The blocks of each chromosome present in either slice, in chromosome order.
+/// A chromosome missing from one slice gets an empty range there, placed
+/// where its block would be.
+fn align_chrom_ranges(events: &[MinEvent], events2: &[MinEvent]) -> Vec<PartitionIndex> {
+    let ranges = group_by_chromosome(events);
+    let ranges2 = group_by_chromosome(events2);
+
+    let mut aligned = Vec::with_capacity(ranges.len().max(ranges2.len()));
+    let (mut i, mut j) = (0usize, 0usize);
+    // Where the next block starts in each slice
+    let (mut pos1, mut pos2) = (0usize, 0usize);
+    while i < ranges.len() || j < ranges2.len() {
+        let chr1 = ranges.get(i).map(|r| r.chr);
+        let chr2 = ranges2.get(j).map(|r| r.chr);
+        let take1 = chr1.is_some() && (chr2.is_none() || chr1 <= chr2);
+        let take2 = chr2.is_some() && (chr1.is_none() || chr2 <= chr1);
+
+        let (start1, end1) = if take1 {
+            i += 1;
+            (ranges[i - 1].start_idx, ranges[i - 1].end_idx)
+        } else {
+            (pos1, pos1)
+        };
+        let (start2, end2) = if take2 {
+            j += 1;
+            (ranges2[j - 1].start_idx, ranges2[j - 1].end_idx)
+        } else {
+            (pos2, pos2)
+        };
+        pos1 = end1;
+        pos2 = end2;
+        aligned.push(PartitionIndex {
+            start1,
+            end1,
+            start2,
+            end2,
+        });
     }
+    aligned
+}
+
+/// Create `num_partitions` partitions for *both* slices, ensuring no chromosome boundaries
+/// are crossed in either slice. Each returned element describes the start/end in slice1
+/// and the start/end in slice2.
+///
+/// The partitions are cut at the same chromosomes in both slices, so partition `i` of
+/// slice1 and partition `i` of slice2 hold the same chromosomes and can be swept on their
+/// own. They follow each other in chromosome order; when there are fewer chromosomes than
+/// partitions the surplus ones are empty. The ranges also apply to any other slice sorted
+/// the same way with the same number of events per chromosome (e.g. the matching ends).
+pub fn partition_two_arrays(
+    sorted_starts: &[MinEvent],
+    sorted_starts2: &[MinEvent],
+    num_partitions: usize,
+) -> Vec<PartitionIndex> {
     if num_partitions == 0 {
         return vec![];
     }
 
-    let chrom_ranges = group_by_chromosome(events);
-
-    // total events
-    let total_len = events.len();
+    let total_len = sorted_starts.len() + sorted_starts2.len();
     let target_chunk_size = (total_len as f64 / num_partitions as f64).ceil() as usize;
 
-    let mut partitions = Vec::with_capacity(num_partitions);
-    let mut current_start = chrom_ranges[0].start_idx;
-    let mut accumulated = 0; // count of events in the current partition
-    let mut partition_count = 1;
-
-    for (i, chr_range) in chrom_ranges.iter().enumerate() {
-        let chr_range_size = chr_range.end_idx - chr_range.start_idx;
-        let tentative_new_size = accumulated + chr_range_size;
-
-        // If adding this chromosome range exceeds target_chunk_size
-        // and we still have space for more partitions, then we close
-        // the current partition before adding this chromosome.
-        if partition_count < num_partitions && // we can only "cut" if we still have partitions left to form
-           accumulated > 0 && // avoid zero-length partition in normal logic
-           (tentative_new_size > target_chunk_size)
-        {
-            // close out the previous partition
-            partitions.push((current_start, chr_range.start_idx));
-            partition_count += 1;
-
-            // start a new partition with this chromosome
-            current_start = chr_range.start_idx;
-            accumulated = 0;
+    let mut partitions: Vec<PartitionIndex> = Vec::with_capacity(num_partitions);
+    let mut accumulated = 0; // count of events in the last partition
+    for chrom in align_chrom_ranges(sorted_starts, sorted_starts2) {
+        let chrom_size = (chrom.end1 - chrom.start1) + (chrom.end2 - chrom.start2);
+
+        // Start a new partition if adding this chromosome would overshoot the
+        // target size and there are still partitions left to form
+        let all_formed = partitions.len() == num_partitions;
+        match partitions.last_mut() {
+            Some(last)
+                if all_formed
+                    || accumulated == 0
+                    || accumulated + chrom_size <= target_chunk_size =>
+            {
+                last.end1 = chrom.end1;
+                last.end2 = chrom.end2;
+            }
+            _ => {
+                partitions.push(chrom);
+                accumulated = 0;
+            }
         }
+        accumulated += chrom_size;
+    }
 
-        accumulated += chr_range_size;
+    // Pad with empty partitions at the end of both slices
+    while partitions.len() < num_partitions {
+        partitions.push(PartitionIndex {
+            start1: sorted_starts.len(),
+            end1: sorted_starts.len(),
+            start2: sorted_starts2.len(),
+            end2: sorted_starts2.len(),
+        });
+    }
 
-        // if this is the last chromosome or if we have formed
-        // the last partition, we close it automatically
-        if i == chrom_ranges.len() - 1 {
-            // close out final partition
-            partitions.push((current_start, chr_range.end_idx));
-        }
+    partitions
+}
+
+/// Like `partition_two_arrays`, but also cuts inside chromosomes, at positions where
+/// neither slice has an interval open (zero joint coverage), so that a dataset
+/// dominated by one chromosome still splits into `num_partitions` balanced parts.
+///
+/// At a cut every interval before it ends at or before the cut position and every
+/// interval after it starts after it, so intervals in different partitions can not
+/// overlap or even touch, and overlap sweeps over the partitions are exact. A nearest
+/// search has to look past the gaps, so it stays with `partition_two_arrays`.
+///
+/// `sorted_ends` and `sorted_ends2` hold the ends of the same intervals as the starts.
+/// Each side of a cut has the same intervals in the starts and in the ends, so the
+/// returned ranges apply to both.
+pub fn partition_at_coverage_gaps(
+    sorted_starts: &[MinEvent],
+    sorted_ends: &[MinEvent],
+    sorted_starts2: &[MinEvent],
+    sorted_ends2: &[MinEvent],
+    num_partitions: usize,
+) -> Vec<PartitionIndex> {
+    if num_partitions == 0 {
+        return vec![];
     }
 
-    // If we still don't have exactly num_partitions, we can pad out
-    // or merge the last ones. This naive approach just merges any
-    // extras at the end if we created more than needed (which can
-    // happen if lots of single-chr partitions blow up).
-    // In many real-world scenarios, you might do a more sophisticated
-    // balancing, but here we keep it simple.
-    if partitions.len() > num_partitions {
-        // Merge the extra partitions into the last one
-        let mut merged = partitions[0..(num_partitions - 1)].to_vec();
-        // The last partition we merge everything from the leftover
-        let last_start = partitions[num_partitions - 1].0;
-        let last_end = partitions.last().unwrap().1;
-        merged.push((last_start, last_end));
-        partitions = merged;
-    } else if partitions.len() < num_partitions {
-        // If fewer partitions, we can just duplicate the last range as no-ops
-        while partitions.len() < num_partitions {
-            let last = *partitions.last().unwrap();
-            partitions.push(last);
+    let total_len = sorted_starts.len() + sorted_starts2.len();
+    let target_chunk_size = (total_len as f64 / num_partitions as f64).ceil() as usize;
+
+    let mut partitions: Vec<PartitionIndex> = Vec::with_capacity(num_partitions);
+    // Where the current partition begins in each slice
+    let (mut start1, mut start2) = (0usize, 0usize);
+    // Starts and ends passed so far in each slice
+    let (mut starts_seen, mut ends_seen) = (0usize, 0usize);
+    let (mut starts_seen2, mut ends_seen2) = (0usize, 0usize);
+
+    let mut events =
+        FourWayMerge::new(sorted_starts, sorted_ends, sorted_starts2, sorted_ends2).peekable();
+    while let Some((which_list, e)) = events.next() {
+        match (which_list.is_first_set(), which_list.is_start()) {
+            (true, true) => starts_seen += 1,
+            (true, false) => ends_seen += 1,
+            (false, true) => starts_seen2 += 1,
+            (false, false) => ends_seen2 += 1,
+        }
+
+        if partitions.len() + 1 == num_partitions
+            || (starts_seen - start1) + (starts_seen2 - start2) < target_chunk_size
+        {
+            continue;
         }
+        // Only cut once every event at this position has been passed, and only
+        // where no interval of either slice is open
+        let position_done = events
+            .peek()
+            .is_some_and(|(_, next)| (next.chr, next.pos) != (e.chr, e.pos));
+        if position_done && starts_seen == ends_seen && starts_seen2 == ends_seen2 {
+            partitions.push(PartitionIndex {
+                start1,
+                end1: starts_seen,
+                start2,
+                end2: starts_seen2,
+            });
+            start1 = starts_seen;
+            start2 = starts_seen2;
+        }
+    }
+    partitions.push(PartitionIndex {
+        start1,
+        end1: sorted_starts.len(),
+        start2,
+        end2: sorted_starts2.len(),
+    });
+
+    // Pad with empty partitions at the end of both slices
+    while partitions.len() < num_partitions {
+        partitions.push(PartitionIndex {
+            start1: sorted_starts.len(),
+            end1: sorted_starts.len(),
+            start2: sorted_starts2.len(),
+            end2: sorted_starts2.len(),
+        });
     }
 
     partitions
 }
 
-/// Create `num_partitions` partitions for *both* slices, ensuring no chromosome boundaries
-/// are crossed in either slice. Each returned element describes the start/end in slice1
-/// and the start/end in slice2.
-pub fn partition_two_arrays(
+/// A rayon pool with `threads` threads, for running partitions on. The pools are
+/// built once per thread count and kept for the life of the process, so calls do
+/// not pay for starting threads. `None` when the pool can not be built (e.g. the
+/// OS refuses more threads); callers then run without it, with the same results.
+pub fn thread_pool(threads: usize) -> Option<Arc<rayon::ThreadPool>> {
+    static POOLS: Mutex<Vec<(usize, Arc<rayon::ThreadPool>)>> = Mutex::new(Vec::new());
+
+    let mut pools = POOLS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some((_, pool)) = pools.iter().find(|(n, _)| *n == threads) {
+        return Some(Arc::clone(pool));
+    }
+    let pool = Arc::new(
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .ok()?,
+    );
+    pools.push((threads, Arc::clone(&pool)));
+    Some(pool)
+}
+
+/// Runs `f` on the pool of `threads` threads, or directly when `threads <= 1`, so
+/// that the rayon iterators in `f` use that many threads.
+pub fn with_threads<R, F>(threads: usize, f: F) -> R
+where
+    R: Send,
+    F: FnOnce() -> R + Send,
+{
+    if threads <= 1 {
+        return f();
+    }
+    match thread_pool(threads) {
+        Some(pool) => pool.install(f),
+        None => f(),
+    }
+}
+
+/// Runs `sweep` on `threads` partitions from `partition_two_arrays` in parallel and
+/// returns the results in partition order, i.e. in chromosome order, so that the
+/// output does not depend on how the threads were scheduled.
+pub fn run_partitioned<R, F>(
     sorted_starts: &[MinEvent],
     sorted_starts2: &[MinEvent],
-    num_partitions: usize,
-) -> Vec<PartitionIndex> {
-    let parts1 = partition_chrom_ranges(sorted_starts, num_partitions);
-    let parts2 = partition_chrom_ranges(sorted_starts2, num_partitions);
-
-    // Zip them into a single vector of PartitionIndex
-    parts1
-        .into_iter()
-        .zip(parts2.into_iter())
-        .map(|((start1, end1), (start2, end2))| PartitionIndex {
-            start1,
-            end1,
-            start2,
-            end2,
-        })
-        .collect()
+    threads: usize,
+    sweep: F,
+) -> Vec<R>
+where
+    R: Send,
+    F: Fn(&PartitionIndex) -> R + Sync,
+{
+    let partitions = partition_two_arrays(sorted_starts, sorted_starts2, threads);
+    run_on_partitions(&partitions, threads, sweep)
+}
+
+/// Runs `sweep` on each of `partitions` in parallel on `threads` threads and returns
+/// the results in partition order.
+pub fn run_on_partitions<R, F>(partitions: &[PartitionIndex], threads: usize, sweep: F) -> Vec<R>
+where
+    R: Send,
+    F: Fn(&PartitionIndex) -> R + Sync,
+{
+    if threads <= 1 {
+        return partitions.iter().map(sweep).collect();
+    }
+    match thread_pool(threads) {
+        Some(pool) => pool.install(|| partitions.par_iter().map(&sweep).collect()),
+        None => partitions.iter().map(sweep).collect(),
+    }
+}
+
+/// Receives the progress of a long sweep.
+pub trait Progress: Sync {
+    /// Called between partitions with the number of start and end events swept so
+    /// far and the number of chromosomes finished. Returning `false` cancels the sweep.
+    fn report(&self, events: usize, chromosomes: usize) -> bool;
+}
+
+impl<F: Fn(usize, usize) -> bool + Sync> Progress for F {
+    fn report(&self, events: usize, chromosomes: usize) -> bool {
+        self(events, chromosomes)
+    }
+}
+
+/// Roughly how many events are swept between two calls to `Progress::report`.
+pub const PROGRESS_EVENTS: usize = 1 << 22;
+
+/// Like `run_on_partitions` over partitions from `partition_at_coverage_gaps`, but
+/// sweeps them `threads` at a time and reports to `progress` after each batch, about
+/// every `PROGRESS_EVENTS` events. A partition can not be interrupted, so a dataset
+/// without coverage gaps is reported on less often. Returns `None` once `progress`
+/// cancels the sweep.
+pub fn run_with_progress<R, F>(
+    sorted_starts: &[MinEvent],
+    sorted_ends: &[MinEvent],
+    sorted_starts2: &[MinEvent],
+    sorted_ends2: &[MinEvent],
+    threads: usize,
+    sweep: F,
+    progress: &dyn Progress,
+) -> Option<Vec<R>>
+where
+    R: Send,
+    F: Fn(&PartitionIndex) -> R + Sync,
+{
+    let threads = threads.max(1);
+    // Every interval has a start and an end event
+    let events = 2 * (sorted_starts.len() + sorted_starts2.len());
+    let batches = events.div_ceil(PROGRESS_EVENTS).max(1);
+    let partitions = partition_at_coverage_gaps(
+        sorted_starts,
+        sorted_ends,
+        sorted_starts2,
+        sorted_ends2,
+        batches * threads,
+    );
+    let chromosomes = align_chrom_ranges(sorted_starts, sorted_starts2);
+    run_batches_with_progress(&partitions, &chromosomes, threads, sweep, progress)
+}
+
+/// Sweeps the contiguous `partitions` `threads` at a time and reports how far the
+/// sweep got after each batch.
+fn run_batches_with_progress<R, F>(
+    partitions: &[PartitionIndex],
+    chromosomes: &[PartitionIndex],
+    threads: usize,
+    sweep: F,
+    progress: &dyn Progress,
+) -> Option<Vec<R>>
+where
+    R: Send,
+    F: Fn(&PartitionIndex) -> R + Sync,
+{
+    let mut results = Vec::with_capacity(partitions.len());
+    for batch in partitions.chunks(threads) {
+        results.extend(run_on_partitions(batch, threads, &sweep));
+
+        // The partitions are contiguous, so everything up to the end of the last
+        // one has been swept
+        let last = batch.last().unwrap();
+        let done = chromosomes.partition_point(|c| c.end1 <= last.end1 && c.end2 <= last.end2);
+        if !progress.report(2 * (last.end1 + last.end2), done) {
+            return None;
+        }
+    }
+    Some(results)
 }
 
 #[cfg(test)]
@@ -278,6 +470,68 @@ mod tests {
         // Here we only check that we got exactly 3 partitions:
         assert_eq!(results.len(), 3);
 
-        // Additional checks or asserts can verify the boundaries do not cross chrs, etc.
+        // Both slices are cut after chr 1; the third partition is empty
+        assert_eq!(
+            (results[0].start1, results[0].end1, results[0].start2, results[0].end2),
+            (0, 5, 0, 3)
+        );
+        assert_eq!(
+            (results[1].start1, results[1].end1, results[1].start2, results[1].end2),
+            (5, 9, 3, 6)
+        );
+        assert_eq!((results[2].start1, results[2].end1), (9, 9));
+    }
+
+    #[test]
+    fn test_partition_at_coverage_gaps() {
+        let event = |chr, pos, idx| MinEvent { chr, pos, idx };
+        // One chromosome; set 1 is [0, 10), [5, 20), [30, 40), [50, 60) and set 2 is
+        // [15, 30), [55, 58). Nothing is open between 40 and 50, but at 30 [15, 30)
+        // meets [30, 40), which must stay in the same partition
+        let starts = vec![event(1, 0, 0), event(1, 5, 1), event(1, 30, 2), event(1, 50, 3)];
+        let ends = vec![event(1, 10, 0), event(1, 20, 1), event(1, 40, 2), event(1, 60, 3)];
+        let starts2 = vec![event(1, 15, 0), event(1, 55, 1)];
+        let ends2 = vec![event(1, 30, 0), event(1, 58, 1)];
+
+        let results = partition_at_coverage_gaps(&starts, &ends, &starts2, &ends2, 2);
+        assert_eq!(results.len(), 2);
+        assert_eq!(
+            (results[0].start1, results[0].end1, results[0].start2, results[0].end2),
+            (0, 3, 0, 1)
+        );
+        assert_eq!(
+            (results[1].start1, results[1].end1, results[1].start2, results[1].end2),
+            (3, 4, 1, 2)
+        );
+    }
+
+    #[test]
+    fn test_thread_pools_are_reused() {
+        let pool = thread_pool(3).unwrap();
+        assert_eq!(pool.current_num_threads(), 3);
+        assert!(Arc::ptr_eq(&pool, &thread_pool(3).unwrap()));
+        assert!(!Arc::ptr_eq(&pool, &thread_pool(2).unwrap()));
+    }
+
+    #[test]
+    fn test_run_with_progress() {
+        let event = |chr, pos, idx| MinEvent { chr, pos, idx };
+        let starts = vec![event(1, 0, 0), event(2, 5, 1)];
+        let ends = vec![event(1, 10, 0), event(2, 20, 1)];
+        let starts2 = vec![event(2, 15, 0), event(3, 0, 1)];
+        let ends2 = vec![event(2, 30, 0), event(3, 5, 1)];
+        let sweep = |p: &PartitionIndex| p.end1 - p.start1 + p.end2 - p.start2;
+
+        let last_report = std::sync::Mutex::new((0, 0));
+        let report = |events, chromosomes| {
+            *last_report.lock().unwrap() = (events, chromosomes);
+            true
+        };
+        let results = run_with_progress(&starts, &ends, &starts2, &ends2, 2, sweep, &report);
+        assert_eq!(results.unwrap().iter().sum::<usize>(), 4);
+        assert_eq!(*last_report.lock().unwrap(), (8, 3));
+
+        let cancel = |_, _| false;
+        assert!(run_with_progress(&starts, &ends, &starts2, &ends2, 2, sweep, &cancel).is_none());
     }
 }
@@ -1,19 +1,25 @@
 use std::{str::FromStr, time::Instant};
 
 use crate::{
-    overlaps::{self, sweep_line_overlaps, sweep_line_overlaps_overlap_pair},
+    multiprocessing,
+    overlaps::{self, points_in_intervals, sweep_line_overlaps_overlap_pair},
     ruranges_structs::{MinEvent, Nearest, OverlapPair},
-    sorts::build_sorted_events_single_collection_separate_outputs,
+    sorts::{self, build_sorted_events_single_collection_separate_outputs},
 };
 
 /// For each MinEvent in `sorted_ends`, find up to `k` *unique positions*
 /// in `sorted_starts2` that lie to the right (including equal position on the
 /// same chromosome). If multiple entries in `sorted_starts2` share the same
 /// position, they all get reported, but they count as one unique position.
+/// If `max_distance` is given, the scan stops at the first start further away
+/// than the cutoff. With `k_by_intervals`, every entry counts towards `k`.
 pub fn nearest_intervals_to_the_right(
-    sorted_ends: Vec<MinEvent>,
-    sorted_starts2: Vec<MinEvent>,
+    sorted_ends: &[MinEvent],
+    sorted_starts2: &[MinEvent],
     k: usize,
+    max_distance: Option<i64>,
+    metric: DistanceMetric,
+    k_by_intervals: bool,
 ) -> Vec<Nearest> {
     // We might need more than `sorted_ends.len()` because each end could
     // contribute up to `k` *unique positions* (potentially multiplied by the
@@ -28,28 +34,16 @@ pub fn nearest_intervals_to_the_right(
     let mut j = 0usize;
 
     // Iterate over each 'end' event
-    for end in &sorted_ends {
+    for end in sorted_ends {
         let end_chr = end.chr;
         let end_pos = end.pos;
 
         // Advance `j` so that sorted_starts2[j] is the first start
         // that is >= end_pos on the same chrom (or beyond).
         // Because both arrays are sorted, we never need to move `j` backward.
-        while j < n_starts {
-            let start = &sorted_starts2[j];
-            if start.chr < end_chr {
-                // still on a smaller chromosome; move j forward
-                j += 1;
-            } else if start.chr == end_chr && start.pos < end_pos {
-                // same chrom but still to the left; move j forward
-                j += 1;
-            } else {
-                // now start.chr > end_chr (i.e. next chromosome) OR
-                // start.chr == end_chr && start.pos >= end_pos
-                // -> we've reached a region that is "to the right" or next chrom
-                break;
-            }
-        }
+        j = gallop(sorted_starts2, j, |start| {
+            (start.chr, start.pos) < (end_chr, end_pos)
+        });
 
         // Now collect up to k unique positions (on the same chromosome).
         let mut unique_count = 0;
@@ -69,7 +63,7 @@ pub fn nearest_intervals_to_the_right(
             }
 
             // Check if we're at a new unique position
-            if last_pos.map_or(true, |lp| start.pos != lp) {
+            if k_by_intervals || last_pos.is_none_or(|lp| start.pos != lp) {
                 unique_count += 1;
                 if unique_count > k {
                     // we've reached the limit of k unique positions
@@ -79,7 +73,12 @@ pub fn nearest_intervals_to_the_right(
             }
 
             // This start is included in the results
-            let distance = start.pos - end_pos + 1; // can be 0 or positive
+            let distance = start.pos - end_pos + metric.offset(); // can be 0 or positive
+
+            // Starts are ascending, so every later one is at least as far away
+            if max_distance.is_some_and(|md| distance > md) {
+                break;
+            }
             output.push(Nearest {
                 distance,
                 idx: end.idx,
@@ -95,45 +94,46 @@ pub fn nearest_intervals_to_the_right(
 
 /// For each MinEvent in `sorted_ends`, find up to `k` *unique positions*
 /// in `sorted_starts2` that lie to the left (strictly smaller position on
-/// the same chromosome). If multiple entries in `sorted_starts2` share
-/// the same position, they all get reported, but they count as one
-/// unique position in the limit `k`.
+/// the same chromosome; with the edge metrics also an equal one, so that a
+/// book-ended interval is found on both sides). If multiple entries in
+/// `sorted_starts2` share the same position, they all get reported, but they
+/// count as one unique position in the limit `k`. If `max_distance` is given,
+/// the scan stops at the first start further away than the cutoff. With
+/// `k_by_intervals`, every entry counts towards `k`.
 pub fn nearest_intervals_to_the_left(
-    sorted_ends: Vec<MinEvent>,
-    sorted_starts2: Vec<MinEvent>,
+    sorted_ends: &[MinEvent],
+    sorted_starts2: &[MinEvent],
     k: usize,
+    max_distance: Option<i64>,
+    metric: DistanceMetric,
+    k_by_intervals: bool,
 ) -> Vec<Nearest> {
     // The max possible size is (number of ends) * (k + duplicates at each of those k positions).
     // We reserve a rough upper bound for efficiency.
     let mut output = Vec::with_capacity(sorted_ends.len().saturating_mul(k));
 
-    let n_starts = sorted_starts2.len();
     let mut j = 0_usize; // Points into sorted_starts2
 
-    for end in &sorted_ends {
+    // An edge ending where the query starts is a neighbour, but two equal
+    // reference points are already reported by the right scan
+    let touching = metric.is_edge();
+
+    for end in sorted_ends {
         let end_chr = end.chr;
         let end_pos = end.pos;
 
         // Move `j` forward so that:
         // - All start events at indices < j have start.chr < end_chr
-        //   OR (start.chr == end_chr && start.pos < end_pos).
+        //   OR (start.chr == end_chr && start.pos < end_pos), or `<=` when touching.
         // - Equivalently, sorted_starts2[j] is the *first* event that is NOT
-        //   strictly to the left of `end`.
-        while j < n_starts {
-            let start = &sorted_starts2[j];
-            if start.chr < end_chr {
-                // still a smaller chromosome => definitely to the left
-                j += 1;
-            } else if start.chr == end_chr && start.pos < end_pos {
-                // same chrom, smaller position => to the left
-                j += 1;
-            } else {
-                // we've reached a start that is not to the left
-                break;
-            }
-        }
+        //   to the left of `end`.
+        j = gallop(sorted_starts2, j, |start| {
+            start.chr < end_chr
+                || (start.chr == end_chr
+                    && (start.pos < end_pos || (touching && start.pos == end_pos)))
+        });
 
-        // Now, everything in [0..j) is strictly to the left of `end`.
+        // Now, everything in [0..j) is to the left of `end`.
         // We'll look backwards from j-1 to gather up to k unique positions
         // on the same chromosome.
         if j == 0 {
@@ -155,7 +155,7 @@ pub fn nearest_intervals_to_the_left(
             }
 
             // Check if we have a new (unique) position
-            if last_pos.map_or(true, |lp| start.pos != lp) {
+            if k_by_intervals || last_pos.is_none_or(|lp| start.pos != lp) {
                 unique_count += 1;
                 if unique_count > k {
                     break;
@@ -163,9 +163,13 @@ pub fn nearest_intervals_to_the_left(
                 last_pos = Some(start.pos);
             }
 
-            // Calculate the distance (end.pos - start.pos)
-            // Here, start.pos < end.pos by definition if we get here.
-            let distance = end_pos - start.pos + 1;
+            // Calculate the distance (end.pos - start.pos, plus the metric's offset)
+            // Here, start.pos <= end.pos by definition if we get here.
+            let distance = end_pos - start.pos + metric.offset();
+            // We are walking leftwards, so every later one is at least as far away
+            if max_distance.is_some_and(|md| distance > md) {
+                break;
+            }
             output.push(Nearest {
                 distance,
                 idx: end.idx,    // the 'end' event's idx
@@ -182,6 +186,24 @@ pub fn nearest_intervals_to_the_left(
     output
 }
 
+/// The first index at or after `from` for which `before` no longer holds, where
+/// `before` holds for a prefix of `events`. Doubles the step until it overshoots
+/// and then binary searches, so moving `d` places costs O(log d): a scan over few
+/// queries skips the candidates between them instead of stepping through each.
+#[inline]
+fn gallop(events: &[MinEvent], from: usize, before: impl Fn(&MinEvent) -> bool) -> usize {
+    let mut lo = from;
+    let mut hi = from;
+    let mut step = 1;
+    while hi < events.len() && before(&events[hi]) {
+        lo = hi + 1;
+        hi += step;
+        step *= 2;
+    }
+    let hi = hi.min(events.len());
+    lo + events[lo..hi].partition_point(before)
+}
+
 /// Merges th
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Direction {
@@ -203,6 +225,117 @@ impl FromStr for Direction {
     }
 }
 
+/// How the distance between a query and a candidate interval is measured.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DistanceMetric {
+    /// Edge to edge in closed coordinates: book-ended intervals are 1 apart.
+    EdgeClosed,
+    /// Edge to edge in half-open coordinates (the bedtools convention):
+    /// the length of the gap, so book-ended intervals are 0 apart.
+    EdgeHalfOpen,
+    /// Absolute difference between the (floored) midpoints.
+    Midpoint,
+    /// Absolute difference between the 5' ends, i.e. `start` on the forward
+    /// strand and `end - 1` on the reverse strand.
+    StartToStart,
+}
+
+impl DistanceMetric {
+    /// Whether the metric is measured between interval edges (as opposed to
+    /// a single reference point per interval).
+    #[inline]
+    pub fn is_edge(&self) -> bool {
+        matches!(
+            self,
+            DistanceMetric::EdgeClosed | DistanceMetric::EdgeHalfOpen
+        )
+    }
+
+    /// What to add to the raw coordinate difference found by the scans.
+    #[inline]
+    pub fn offset(&self) -> i64 {
+        match self {
+            DistanceMetric::EdgeClosed => 1,
+            _ => 0,
+        }
+    }
+
+    /// The single position each interval is measured from, for the point metrics.
+    pub fn reference_points(
+        &self,
+        starts: &[i64],
+        ends: &[i64],
+        strand_flags: Option<&[bool]>,
+    ) -> Vec<i64> {
+        (0..starts.len())
+            .map(|i| match self {
+                DistanceMetric::StartToStart if strand_flags.is_some_and(|f| !f[i]) => ends[i] - 1,
+                DistanceMetric::StartToStart => starts[i],
+                _ => starts[i] + (ends[i] - starts[i]) / 2,
+            })
+            .collect()
+    }
+}
+
+impl FromStr for DistanceMetric {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "edge" | "closed" => Ok(DistanceMetric::EdgeClosed),
+            "half_open" | "bedtools" => Ok(DistanceMetric::EdgeHalfOpen),
+            "midpoint" => Ok(DistanceMetric::Midpoint),
+            "start" | "tss" => Ok(DistanceMetric::StartToStart),
+            _ => Err("Invalid distance metric string"),
+        }
+    }
+}
+
+/// The `(idx, idx2, distance)` columns of a nearest search.
+pub type NearestPairs = (Vec<u32>, Vec<u32>, Vec<i64>);
+
+/// The options of `nearest`. The default finds the single closest interval on
+/// either side by the closed edge distance, overlaps included.
+#[derive(Debug, Clone, Copy)]
+pub struct NearestOptions<'a> {
+    pub slack: i64,
+    pub k: usize,
+    pub include_overlaps: bool,
+    pub direction: &'a str,
+    pub max_distance: Option<i64>,
+    pub distance_metric: &'a str,
+    pub strand_flags: Option<&'a [bool]>,
+    pub strand_flags2: Option<&'a [bool]>,
+    pub k_by_intervals: bool,
+    pub threads: usize,
+}
+
+impl Default for NearestOptions<'_> {
+    fn default() -> Self {
+        NearestOptions {
+            slack: 0,
+            k: 1,
+            include_overlaps: true,
+            direction: "any",
+            max_distance: None,
+            distance_metric: "edge",
+            strand_flags: None,
+            strand_flags2: None,
+            k_by_intervals: false,
+            threads: 1,
+        }
+    }
+}
+
+/// With the point metrics (`midpoint`, `start`) an overlapping interval is just
+/// another candidate at its point distance and there are no edges to extend,
+/// so `include_overlaps = false` and a non-zero `slack` are rejected for them.
+/// `strand_flags`/`strand_flags2` (true = forward) are only used by the `start`
+/// metric; without them every interval is forward. Unknown `direction` or
+/// `distance_metric` strings are errors as well.
+///
+/// With `threads > 1` the chromosomes are split into that many partitions that
+/// are searched in parallel; the result is the same as with one thread.
 pub fn nearest(
     chrs: &[u32],
     starts: &[i64],
@@ -210,51 +343,463 @@ pub fn nearest(
     chrs2: &[u32],
     starts2: &[i64],
     ends2: &[i64],
-    slack: i64,
-    k: usize,
-    include_overlaps: bool,
-    direction: &str,
-) -> (Vec<u32>, Vec<u32>, Vec<i64>) {
-    let dir = Direction::from_str(direction).unwrap();
+    options: &NearestOptions,
+) -> Result<NearestPairs, &'static str> {
+    let result = nearest_with_progress(chrs, starts, ends, chrs2, starts2, ends2, options, None)?;
+    Ok(result.expect("a search without progress is never cancelled"))
+}
 
-    let sorted_starts = build_sorted_events_single_collection_separate_outputs(chrs, starts, slack);
-    let sorted_ends = build_sorted_events_single_collection_separate_outputs(chrs, ends, slack);
+/// Like `nearest`, but searches the neighbours in chunks of about
+/// `PROGRESS_EVENTS / 2` queries per thread and reports to `progress` after each
+/// batch, with two events per query searched so far and the chromosomes the last
+/// scan has finished. Returns `Ok(None)` once `progress` cancels the search. Only
+/// the overlap sweep before the scans can not be interrupted. The result does not
+/// depend on `progress`.
+pub fn nearest_with_progress(
+    chrs: &[u32],
+    starts: &[i64],
+    ends: &[i64],
+    chrs2: &[u32],
+    starts2: &[i64],
+    ends2: &[i64],
+    options: &NearestOptions,
+    progress: Option<&dyn multiprocessing::Progress>,
+) -> Result<Option<NearestPairs>, &'static str> {
+    let NearestOptions {
+        slack,
+        k,
+        include_overlaps,
+        direction,
+        max_distance,
+        distance_metric,
+        strand_flags,
+        strand_flags2,
+        k_by_intervals,
+        threads,
+    } = *options;
+    let dir = Direction::from_str(direction)?;
+    let metric = DistanceMetric::from_str(distance_metric)?;
+    if !metric.is_edge() && slack != 0 {
+        return Err("slack only applies to the edge distance metrics");
+    }
+    if !metric.is_edge() && !include_overlaps {
+        return Err("include_overlaps=False only applies to the edge distance metrics");
+    }
 
-    let sorted_starts2 = build_sorted_events_single_collection_separate_outputs(chrs2, starts2, 0);
-    let sorted_ends2 = build_sorted_events_single_collection_separate_outputs(chrs2, ends2, 0);
+    // (query events, candidate events) for the left and the right scan
+    let (left_query, left_candidates, right_query, right_candidates) = if metric.is_edge() {
+        let (starts, ends) = overlaps::sweep_coordinates(starts, ends, slack, slack, None, false);
+        let sorted_starts = sorts::build_sorted_min_events(chrs, &starts, threads);
+        let sorted_ends = sorts::build_sorted_min_events(chrs, &ends, threads);
+
+        let sorted_starts2 = sorts::build_sorted_min_events(chrs2, starts2, threads);
+        let sorted_ends2 = sorts::build_sorted_min_events(chrs2, ends2, threads);
+        (sorted_starts, sorted_ends2, sorted_ends, sorted_starts2)
+    } else {
+        let points = metric.reference_points(starts, ends, strand_flags);
+        let points2 = metric.reference_points(starts2, ends2, strand_flags2);
+        let sorted_points = sorts::build_sorted_min_events(chrs, &points, threads);
+        let sorted_points2 = sorts::build_sorted_min_events(chrs2, &points2, threads);
+        (
+            sorted_points.clone(),
+            sorted_points2.clone(),
+            sorted_points,
+            sorted_points2,
+        )
+    };
+
+    // The query starts/ends and candidate starts/ends are the four lists of the overlap sweep
+    let search = |left_query: &[MinEvent],
+                  left_candidates: &[MinEvent],
+                  right_query: &[MinEvent],
+                  right_candidates: &[MinEvent]| {
+        let mut overlaps = if include_overlaps && metric.is_edge() {
+            sweep_line_overlaps_overlap_pair(
+                left_query,
+                right_query,
+                right_candidates,
+                left_candidates,
+            )
+        } else {
+            Vec::new()
+        };
+        radsort::sort_by_key(&mut overlaps, |p| p.idx);
 
-    let overlaps = if include_overlaps {
-        sweep_line_overlaps_overlap_pair(
-            &sorted_starts,
-            &sorted_ends,
-            &sorted_starts2,
-            &sorted_ends2,
+        nearest_from_sorted_events(
+            left_query,
+            left_candidates,
+            right_query,
+            right_candidates,
+            &overlaps,
+            dir,
+            k,
+            max_distance,
+            metric,
+            k_by_intervals,
         )
+    };
+
+    if threads <= 1 && progress.is_none() {
+        return Ok(Some(search(
+            &left_query,
+            &left_candidates,
+            &right_query,
+            &right_candidates,
+        )));
+    }
+
+    // The query and candidate lists have the same chromosome blocks on both
+    // sides, so one partitioning applies to all four
+    let sweep = |p: &multiprocessing::PartitionIndex| {
+        search(
+            &left_query[p.start1..p.end1],
+            &left_candidates[p.start2..p.end2],
+            &right_query[p.start1..p.end1],
+            &right_candidates[p.start2..p.end2],
+        )
+    };
+    let Some(progress) = progress else {
+        let parts = multiprocessing::run_partitioned(&left_query, &left_candidates, threads, sweep);
+        return Ok(Some(concat_partitions(parts)));
+    };
+
+    let mut overlaps = if include_overlaps && metric.is_edge() {
+        multiprocessing::run_partitioned(&left_query, &left_candidates, threads, |p| {
+            sweep_line_overlaps_overlap_pair(
+                &left_query[p.start1..p.end1],
+                &right_query[p.start1..p.end1],
+                &right_candidates[p.start2..p.end2],
+                &left_candidates[p.start2..p.end2],
+            )
+        })
+        .concat()
     } else {
         Vec::new()
     };
+    radsort::sort_by_key(&mut overlaps, |p| p.idx);
+
+    // The scans look at every query on its own, so they can be cut into chunks
+    // anywhere, inside a chromosome too. Both query lists have the same chromosome blocks
+    let chrom_ends: Vec<usize> = (1..=left_query.len())
+        .filter(|&i| i == left_query.len() || left_query[i].chr != left_query[i - 1].chr)
+        .collect();
+    let finished = |done: usize| chrom_ends.partition_point(|&end| end <= done);
+    let scan_left = dir != Direction::Forward;
+    let scan_right = dir != Direction::Backward;
+    let scans = usize::from(scan_left) + usize::from(scan_right);
+
+    let nearest_left = if scan_left {
+        let scan = |queries: &[MinEvent]| {
+            nearest_intervals_to_the_left(
+                queries,
+                &left_candidates,
+                k,
+                max_distance,
+                metric,
+                k_by_intervals,
+            )
+        };
+        let report = |done| {
+            let chromosomes = if scan_right { 0 } else { finished(done) };
+            progress.report(2 * done / scans, chromosomes)
+        };
+        let Some(mut found) = scan_in_chunks(&left_query, threads, scan, report) else {
+            return Ok(None);
+        };
+        radsort::sort_by_key(&mut found, |n| (n.idx, n.distance));
+        found
+    } else {
+        Vec::new()
+    };
+    let nearest_right = if scan_right {
+        let scan = |queries: &[MinEvent]| {
+            nearest_intervals_to_the_right(
+                queries,
+                &right_candidates,
+                k,
+                max_distance,
+                metric,
+                k_by_intervals,
+            )
+        };
+        let before = if scan_left { left_query.len() } else { 0 };
+        let report = |done| progress.report(2 * (before + done) / scans, finished(done));
+        let Some(mut found) = scan_in_chunks(&right_query, threads, scan, report) else {
+            return Ok(None);
+        };
+        radsort::sort_by_key(&mut found, |n| (n.idx, n.distance));
+        found
+    } else {
+        Vec::new()
+    };
+
+    Ok(Some(merge_three_way_by_index_distance(
+        &overlaps,
+        &nearest_left,
+        &nearest_right,
+        k,
+        k_by_intervals,
+    )))
+}
+
+/// The results of the partitions of a search, in the single-threaded order.
+fn concat_partitions(parts: Vec<(Vec<u32>, Vec<u32>, Vec<i64>)>) -> NearestPairs {
+    let mut idxs = Vec::new();
+    let mut idxs2 = Vec::new();
+    let mut distances = Vec::new();
+    for (part_idxs, part_idxs2, part_distances) in parts {
+        idxs.extend(part_idxs);
+        idxs2.extend(part_idxs2);
+        distances.extend(part_distances);
+    }
+
+    // Each partition is sorted by query; a stable sort on the query restores
+    // the single-threaded order
+    let mut rows: Vec<usize> = (0..idxs.len()).collect();
+    radsort::sort_by_key(&mut rows, |&r| idxs[r]);
+    (
+        sorts::permute(&idxs, &rows),
+        sorts::permute(&idxs2, &rows),
+        sorts::permute(&distances, &rows),
+    )
+}
+
+/// Runs `scan` on consecutive chunks of about `PROGRESS_EVENTS / 2` of
+/// `queries`, `threads` chunks at a time, and calls `report` with the number of
+/// queries scanned after each batch. The results are in the order of one scan
+/// over all of `queries`, or `None` once `report` returns false.
+fn scan_in_chunks(
+    queries: &[MinEvent],
+    threads: usize,
+    scan: impl Fn(&[MinEvent]) -> Vec<Nearest> + Sync,
+    report: impl Fn(usize) -> bool,
+) -> Option<Vec<Nearest>> {
+    let chunk = multiprocessing::PROGRESS_EVENTS / 2;
+    let chunks: Vec<multiprocessing::PartitionIndex> = (0..queries.len())
+        .step_by(chunk)
+        .map(|start| multiprocessing::PartitionIndex {
+            start1: start,
+            end1: (start + chunk).min(queries.len()),
+            start2: 0,
+            end2: 0,
+        })
+        .collect();
+    let mut found = Vec::new();
+    for batch in chunks.chunks(threads.max(1)) {
+        let parts = multiprocessing::run_on_partitions(batch, threads, |p| {
+            scan(&queries[p.start1..p.end1])
+        });
+        found.extend(parts.into_iter().flatten());
+        if !report(batch.last().unwrap().end1) {
+            return None;
+        }
+    }
+    Some(found)
+}
+
+/// Like `nearest`, but every query is a single position, treated as the 1-bp
+/// interval `[pos, pos + 1)`. Overlaps are the intervals containing the position.
+/// Unknown `direction` or `distance_metric` strings are errors, as is
+/// `include_overlaps = false` with a point metric. `slack`, `strand_flags` and
+/// `threads` are not used.
+pub fn nearest_points(
+    chrs: &[u32],
+    positions: &[i64],
+    chrs2: &[u32],
+    starts2: &[i64],
+    ends2: &[i64],
+    options: &NearestOptions,
+) -> Result<NearestPairs, &'static str> {
+    let NearestOptions {
+        k,
+        include_overlaps,
+        direction,
+        max_distance,
+        distance_metric,
+        strand_flags2,
+        k_by_intervals,
+        ..
+    } = *options;
+    let dir = Direction::from_str(direction)?;
+    let metric = DistanceMetric::from_str(distance_metric)?;
+    if !metric.is_edge() && !include_overlaps {
+        return Err("include_overlaps=False only applies to the edge distance metrics");
+    }
+
+    let sorted_positions =
+        build_sorted_events_single_collection_separate_outputs(chrs, positions, 0);
+
+    let (left_query, left_candidates, right_query, right_candidates, overlaps) = if metric.is_edge()
+    {
+        let sorted_starts2 =
+            build_sorted_events_single_collection_separate_outputs(chrs2, starts2, 0);
+        let sorted_ends2 = build_sorted_events_single_collection_separate_outputs(chrs2, ends2, 0);
+
+        let overlaps = if include_overlaps {
+            let mut pairs = points_in_intervals(chrs, positions, chrs2, starts2, ends2, 0);
+            radsort::sort_by_key(&mut pairs, |p| p.idx);
+            pairs
+        } else {
+            Vec::new()
+        };
+        // The right scan measures from the end of the 1-bp interval
+        let sorted_position_ends = sorted_positions
+            .iter()
+            .map(|e| MinEvent {
+                chr: e.chr,
+                pos: e.pos + 1,
+                idx: e.idx,
+            })
+            .collect();
+        (
+            sorted_positions,
+            sorted_ends2,
+            sorted_position_ends,
+            sorted_starts2,
+            overlaps,
+        )
+    } else {
+        let points2 = metric.reference_points(starts2, ends2, strand_flags2);
+        let sorted_points2 =
+            build_sorted_events_single_collection_separate_outputs(chrs2, &points2, 0);
+        (
+            sorted_positions.clone(),
+            sorted_points2.clone(),
+            sorted_positions,
+            sorted_points2,
+            Vec::new(),
+        )
+    };
+
+    Ok(nearest_from_sorted_events(
+        &left_query,
+        &left_candidates,
+        &right_query,
+        &right_candidates,
+        &overlaps,
+        dir,
+        k,
+        max_distance,
+        metric,
+        k_by_intervals,
+    ))
+}
+
+/// Runs the left/right scans requested by `dir` and merges them with the
+/// (idx-sorted) overlaps into at most `k` neighbours per query.
+pub fn nearest_from_sorted_events(
+    left_query: &[MinEvent],
+    left_candidates: &[MinEvent],
+    right_query: &[MinEvent],
+    right_candidates: &[MinEvent],
+    overlaps: &[OverlapPair],
+    dir: Direction,
+    k: usize,
+    max_distance: Option<i64>,
+    metric: DistanceMetric,
+    k_by_intervals: bool,
+) -> (Vec<u32>, Vec<u32>, Vec<i64>) {
     let nearest_left = if dir == Direction::Backward || dir == Direction::Any {
-        let mut tmp = nearest_intervals_to_the_left(sorted_starts, sorted_ends2, k);
+        let mut tmp = nearest_intervals_to_the_left(
+            left_query,
+            left_candidates,
+            k,
+            max_distance,
+            metric,
+            k_by_intervals,
+        );
         radsort::sort_by_key(&mut tmp, |n| (n.idx, n.distance));
         tmp
     } else {
         Vec::new()
     };
     let nearest_right = if dir == Direction::Forward || dir == Direction::Any {
-        let mut tmp = nearest_intervals_to_the_right(sorted_ends, sorted_starts2, k);
+        let mut tmp = nearest_intervals_to_the_right(
+            right_query,
+            right_candidates,
+            k,
+            max_distance,
+            metric,
+            k_by_intervals,
+        );
         radsort::sort_by_key(&mut tmp, |n| (n.idx, n.distance));
         tmp
     } else {
         Vec::new()
     };
 
-    let merged = merge_three_way_by_index_distance(&overlaps, &nearest_left, &nearest_right, k);
-    merged
+    merge_three_way_by_index_distance(overlaps, &nearest_left, &nearest_right, k, k_by_intervals)
+}
+
+/// For each query interval, the closest candidate to the left and the closest
+/// candidate to the right, one row per query in input order:
+/// `(left_idx, left_distance, right_idx, right_distance, overlaps)`.
+/// Missing neighbours are reported as -1 in both the index and the distance
+/// column; `overlaps` flags queries that overlap at least one candidate. The
+/// queries are extended by `slack` on both sides first.
+pub fn flanking_neighbours(
+    chrs: &[u32],
+    starts: &[i64],
+    ends: &[i64],
+    chrs2: &[u32],
+    starts2: &[i64],
+    ends2: &[i64],
+    slack: i64,
+    max_distance: Option<i64>,
+) -> (Vec<i64>, Vec<i64>, Vec<i64>, Vec<i64>, Vec<bool>) {
+    let n = chrs.len();
+    let mut left_idx = vec![-1; n];
+    let mut left_distance = vec![-1; n];
+    let mut right_idx = vec![-1; n];
+    let mut right_distance = vec![-1; n];
+    let mut overlaps = vec![false; n];
+
+    let (sorted_starts, sorted_ends) =
+        overlaps::compute_sorted_events(chrs, starts, ends, slack, slack, None, false);
+
+    let sorted_starts2 = build_sorted_events_single_collection_separate_outputs(chrs2, starts2, 0);
+    let sorted_ends2 = build_sorted_events_single_collection_separate_outputs(chrs2, ends2, 0);
+
+    for pair in sweep_line_overlaps_overlap_pair(
+        &sorted_starts,
+        &sorted_ends,
+        &sorted_starts2,
+        &sorted_ends2,
+    ) {
+        overlaps[pair.idx as usize] = true;
+    }
+
+    // With k = 1 counted by intervals each query gets at most one row per side
+    for hit in nearest_intervals_to_the_left(
+        &sorted_starts,
+        &sorted_ends2,
+        1,
+        max_distance,
+        DistanceMetric::EdgeClosed,
+        true,
+    ) {
+        left_idx[hit.idx as usize] = hit.idx2 as i64;
+        left_distance[hit.idx as usize] = hit.distance;
+    }
+    for hit in nearest_intervals_to_the_right(
+        &sorted_ends,
+        &sorted_starts2,
+        1,
+        max_distance,
+        DistanceMetric::EdgeClosed,
+        true,
+    ) {
+        right_idx[hit.idx as usize] = hit.idx2 as i64;
+        right_distance[hit.idx as usize] = hit.distance;
+    }
+
+    (left_idx, left_distance, right_idx, right_distance, overlaps)
 }
 
 /// Merges three sources of intervals, grouped by `idx` (i.e. `idx1` in overlaps).
 /// For each unique `idx`, it returns up to `k` *distinct* distances (including
-/// all intervals at those distances). Overlaps are treated as distance=0 (or 1).
+/// all intervals at those distances), or up to `k` intervals if `k_by_intervals`.
+/// Overlaps are treated as distance=0.
 ///
 /// The data is assumed to be sorted in ascending order by `(idx, distance)`.
 pub fn merge_three_way_by_index_distance(
@@ -262,6 +807,7 @@ pub fn merge_three_way_by_index_distance(
     nearest_left: &[Nearest],  // sorted by (idx, distance)
     nearest_right: &[Nearest], // sorted by (idx, distance)
     k: usize,
+    k_by_intervals: bool,
 ) -> (Vec<u32>, Vec<u32>, Vec<i64>) {
     // We'll return tuples: (idx, idx2, distance).
     // You can adapt if you want a custom struct instead.
@@ -326,30 +872,22 @@ pub fn merge_three_way_by_index_distance(
 
         let (mut oi, mut lj, mut rr) = (0, 0, 0);
 
-        // Helper closures to peek distance from each slice
-        let mut overlap_dist = |ix: usize| -> i64 {
-            // If you store distance in OverlapPair, return that. Otherwise 0 or 1.
-            // For the example, let's assume actual Overlap distance=0:
-            0
-        };
-        let mut left_dist = |ix: usize| -> i64 { left_slice[ix].distance };
-        let mut right_dist = |ix: usize| -> i64 { right_slice[ix].distance };
-
+        // Overlaps are at distance 0
         // Inner loop: pick the next *smallest* distance among the three slices
         while oi < overlaps_slice.len() || lj < left_slice.len() || rr < right_slice.len() {
             // Peek next distance (or i64::MAX if none)
             let d_o = if oi < overlaps_slice.len() {
-                overlap_dist(oi)
+                0
             } else {
                 i64::MAX
             };
             let d_l = if lj < left_slice.len() {
-                left_dist(lj)
+                left_slice[lj].distance
             } else {
                 i64::MAX
             };
             let d_r = if rr < right_slice.len() {
-                right_dist(rr)
+                right_slice[rr].distance
             } else {
                 i64::MAX
             };
@@ -362,11 +900,11 @@ pub fn merge_three_way_by_index_distance(
 
             // We'll pull everything from Overlaps that has distance == smallest
             while oi < overlaps_slice.len() {
-                let dcur = overlap_dist(oi);
+                let dcur = 0;
                 if dcur == smallest {
                     // If this is a *new* distance (not in used_distances),
                     // we check if it would exceed k distinct distances
-                    if !used_distances.contains(&dcur) {
+                    if k_by_intervals || !used_distances.contains(&dcur) {
                         distinct_count += 1;
                         if distinct_count > k {
                             // no new distances allowed
@@ -390,9 +928,9 @@ pub fn merge_three_way_by_index_distance(
 
             // Pull everything from Left that has distance == smallest
             while lj < left_slice.len() {
-                let dcur = left_dist(lj);
+                let dcur = left_slice[lj].distance;
                 if dcur == smallest {
-                    if !used_distances.contains(&dcur) {
+                    if k_by_intervals || !used_distances.contains(&dcur) {
                         distinct_count += 1;
                         if distinct_count > k {
                             break;
@@ -414,9 +952,9 @@ pub fn merge_three_way_by_index_distance(
 
             // Pull everything from Right that has distance == smallest
             while rr < right_slice.len() {
-                let dcur = right_dist(rr);
+                let dcur = right_slice[rr].distance;
                 if dcur == smallest {
-                    if !used_distances.contains(&dcur) {
+                    if k_by_intervals || !used_distances.contains(&dcur) {
                         distinct_count += 1;
                         if distinct_count > k {
                             break;
@@ -441,3 +979,213 @@ pub fn merge_three_way_by_index_distance(
 
     (idxs1, idxs2, distance)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events(chr: u32, positions: &[i64]) -> Vec<MinEvent> {
+        positions
+            .iter()
+            .enumerate()
+            .map(|(idx, &pos)| MinEvent {
+                chr,
+                pos,
+                idx: idx as u32,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_max_distance_cuts_off_the_scans() {
+        let query_ends = events(1, &[10]);
+        let starts2 = events(1, &[12, 20, 40]);
+        let right = |max_distance| {
+            nearest_intervals_to_the_right(
+                &query_ends,
+                &starts2,
+                3,
+                max_distance,
+                DistanceMetric::EdgeClosed,
+                false,
+            )
+            .iter()
+            .map(|n| (n.idx2, n.distance))
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(right(None), vec![(0, 3), (1, 11), (2, 31)]);
+        assert_eq!(right(Some(11)), vec![(0, 3), (1, 11)]);
+        assert_eq!(right(Some(2)), vec![]);
+
+        let query_starts = events(1, &[50]);
+        let ends2 = events(1, &[30, 45]);
+        let left = |max_distance| {
+            nearest_intervals_to_the_left(
+                &query_starts,
+                &ends2,
+                3,
+                max_distance,
+                DistanceMetric::EdgeClosed,
+                false,
+            )
+            .iter()
+            .map(|n| (n.idx2, n.distance))
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(left(None), vec![(1, 6), (0, 21)]);
+        assert_eq!(left(Some(10)), vec![(1, 6)]);
+    }
+
+    #[test]
+    fn test_max_distance_in_nearest() {
+        // Queries [10, 20) and [100, 110); candidates [25, 30) and [0, 2)
+        let run = |max_distance| {
+            nearest(
+                &[1, 1],
+                &[10, 100],
+                &[20, 110],
+                &[1, 1],
+                &[25, 0],
+                &[30, 2],
+                &NearestOptions {
+                    max_distance,
+                    ..NearestOptions::default()
+                },
+            )
+        };
+        assert_eq!(run(None), Ok((vec![0, 1], vec![0, 0], vec![6, 71])));
+        assert_eq!(run(Some(10)), Ok((vec![0], vec![0], vec![6])));
+    }
+
+    #[test]
+    fn test_book_ended_neighbours_are_found_on_both_sides() {
+        // The query [10, 20) touches [0, 10) on the left and [20, 30) on the right
+        let run = |metric| {
+            nearest(
+                &[1],
+                &[10],
+                &[20],
+                &[1, 1],
+                &[0, 20],
+                &[10, 30],
+                &NearestOptions {
+                    distance_metric: metric,
+                    ..NearestOptions::default()
+                },
+            )
+        };
+        assert_eq!(run("half_open"), Ok((vec![0, 0], vec![0, 1], vec![0, 0])));
+        assert_eq!(run("closed"), Ok((vec![0, 0], vec![0, 1], vec![1, 1])));
+    }
+
+    #[test]
+    fn test_flanking_neighbours_extend_the_queries() {
+        // With slack 5 the query [10, 20) becomes [5, 25): it overlaps [22, 24),
+        // [0, 3) ends 3 before it and [30, 40) starts 6 after it (closed)
+        let (left_idx, left_distance, right_idx, right_distance, overlaps) = flanking_neighbours(
+            &[1],
+            &[10],
+            &[20],
+            &[1, 1, 1],
+            &[0, 22, 30],
+            &[3, 24, 40],
+            5,
+            None,
+        );
+        assert_eq!((left_idx, left_distance), (vec![0], vec![3]));
+        assert_eq!((right_idx, right_distance), (vec![2], vec![6]));
+        assert_eq!(overlaps, vec![true]);
+    }
+
+    #[test]
+    fn test_nearest_points_book_ended_on_both_sides() {
+        // The position 10 is the 1-bp interval [10, 11): [5, 10) ends where it
+        // starts and [11, 15) starts where it ends
+        let run = |metric, include_overlaps| {
+            nearest_points(
+                &[1],
+                &[10],
+                &[1, 1],
+                &[5, 11],
+                &[10, 15],
+                &NearestOptions {
+                    include_overlaps,
+                    distance_metric: metric,
+                    ..NearestOptions::default()
+                },
+            )
+        };
+        assert_eq!(
+            run("half_open", true),
+            Ok((vec![0, 0], vec![0, 1], vec![0, 0]))
+        );
+        assert_eq!(
+            run("closed", true),
+            Ok((vec![0, 0], vec![0, 1], vec![1, 1]))
+        );
+        assert!(run("midpoint", false).is_err());
+        assert!(run("nowhere", true).is_err());
+    }
+
+    #[test]
+    fn test_invalid_arguments_are_errors() {
+        let run = |slack, include_overlaps, direction, metric| {
+            nearest(
+                &[1],
+                &[10],
+                &[20],
+                &[1],
+                &[0],
+                &[5],
+                &NearestOptions {
+                    slack,
+                    include_overlaps,
+                    direction,
+                    distance_metric: metric,
+                    ..NearestOptions::default()
+                },
+            )
+        };
+        assert!(run(0, true, "sideways", "edge").is_err());
+        assert!(run(0, true, "any", "furthest").is_err());
+        assert!(run(5, true, "any", "midpoint").is_err());
+        assert!(run(0, false, "any", "start").is_err());
+        assert!(run(5, false, "any", "edge").is_ok());
+    }
+
+    #[test]
+    fn test_nearest_progress_does_not_change_the_result() {
+        let mut rng = crate::test_util::Rng::new(41);
+        let (chrs, starts, ends) = crate::test_util::random_intervals(&mut rng, 40, 3, 300, 20);
+        let (chrs2, starts2, ends2) = crate::test_util::random_intervals(&mut rng, 60, 3, 300, 20);
+        let run = |threads, progress: Option<&dyn multiprocessing::Progress>| {
+            let options = NearestOptions {
+                k: 2,
+                threads,
+                ..NearestOptions::default()
+            };
+            nearest_with_progress(
+                &chrs, &starts, &ends, &chrs2, &starts2, &ends2, &options, progress,
+            )
+            .unwrap()
+        };
+        let expected = run(1, None).unwrap();
+        let reported = std::sync::Mutex::new((0, 0));
+        let report = |events, chromosomes| {
+            *reported.lock().unwrap() = (events, chromosomes);
+            true
+        };
+        let cancel = |_, _| false;
+        let mut query_chrs = chrs.clone();
+        query_chrs.sort_unstable();
+        query_chrs.dedup();
+        for threads in [1, 3] {
+            assert_eq!(run(threads, Some(&report)), Some(expected.clone()));
+            assert_eq!(
+                *reported.lock().unwrap(),
+                (2 * chrs.len(), query_chrs.len())
+            );
+            assert_eq!(run(threads, Some(&cancel)), None);
+        }
+    }
+}
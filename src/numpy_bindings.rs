@@ -1,7 +1,9 @@
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
-use numpy::PyArrayMethods;
+use numpy::{Element, PyArrayMethods, PyUntypedArrayMethods};
 use numpy::{IntoPyArray, PyArray1, PyReadonlyArray1};
 use pyo3::prelude::*;
 use pyo3::types::PyDict;
@@ -11,18 +13,22 @@ use rustc_hash::FxHashMap;
 use rustc_hash::FxHashSet;
 
 use crate::boundary::sweep_line_boundary;
-use crate::cluster::sweep_line_cluster;
+use crate::cluster::sweep_line_cluster_into;
 use crate::complement::sweep_line_non_overlaps;
 use crate::complement_single::sweep_line_complement;
+use crate::coverage;
+use crate::interval_index::IntervalIndex;
 use crate::merge::sweep_line_merge;
-use crate::nearest::nearest;
+use crate::multiprocessing::{self, PartitionIndex, Progress};
+use crate::nearest::{flanking_neighbours, nearest_points, nearest_with_progress, NearestOptions};
 // use crate::nearest::nearest;
-use crate::overlaps::{self, sweep_line_overlaps_containment};
+use crate::overlaps::{self, points_in_intervals, OverlapRelation};
 use crate::ruranges_structs::OverlapPair;
 use crate::sorts;
-use crate::sorts::build_sorted_events_single_collection_separate_outputs;
+use crate::sorts::OutputOrder;
 use crate::spliced_subsequence::spliced_subseq;
 use crate::split::sweep_line_split;
+use crate::streaming;
 use crate::subtract::sweep_line_subtract;
 use crate::tile::{tile, window};
 
@@ -41,12 +47,19 @@ impl FromStr for OverlapType {
             "all" => Ok(OverlapType::All),
             "first" => Ok(OverlapType::First),
             "last" => Ok(OverlapType::Last),
-            _ => Err("Invalid direction string"),
+            _ => Err("Invalid overlap type string"),
         }
     }
 }
 
+/// With a `progress` callback, `progress(events, chromosomes)` is called every
+/// few million events. With a callback or `interruptible`, Ctrl-C stops the sweep
+/// with `KeyboardInterrupt`, and an exception raised by the callback cancels it.
+/// Both are only checked where the intervals of both sets leave a gap, so a
+/// dataset without coverage gaps is only checked once it is done. Neither changes
+/// the result. Functions without these arguments can not be interrupted.
 #[pyfunction]
+#[pyo3(signature = (chrs, starts, ends, chrs2, starts2, ends2, slack=0, overlap_type="all", contained=false, slack_left=None, slack_right=None, strand_flags=None, overlap_relation=None, order="input", threads=1, progress=None, interruptible=false))]
 pub fn chromsweep_numpy(
     py: Python,
     chrs: PyReadonlyArray1<u32>,
@@ -58,74 +71,619 @@ pub fn chromsweep_numpy(
     slack: i64,
     overlap_type: &str,
     contained: bool,
+    slack_left: Option<i64>,
+    slack_right: Option<i64>,
+    strand_flags: Option<PyReadonlyArray1<bool>>,
+    overlap_relation: Option<&str>,
+    order: &str,
+    threads: usize,
+    progress: Option<Py<PyAny>>,
+    interruptible: bool,
 ) -> PyResult<(Py<PyArray1<u32>>, Py<PyArray1<u32>>)> {
-    let chrs_slice = chrs.as_slice()?;
-    let starts_slice = starts.as_slice()?;
-    let ends_slice = ends.as_slice()?;
-    let chrs_slice2 = chrs2.as_slice()?;
-    let starts_slice2 = starts2.as_slice()?;
-    let ends_slice2 = ends2.as_slice()?;
-
-    let overlap_type = OverlapType::from_str(overlap_type).unwrap();
-    let invert = overlap_type == OverlapType::Last;
+    let chrs_slice = &owned(chrs);
+    let starts_slice = &owned(starts);
+    let ends_slice = &owned(ends);
+    let chrs_slice2 = &owned(chrs2);
+    let starts_slice2 = &owned(starts2);
+    let ends_slice2 = &owned(ends2);
+    let strand_flags = strand_flags.map(owned);
+    let strand_slice = strand_flags.as_deref();
+    check_strand_flags("strand_flags", strand_slice, chrs_slice.len())?;
+
+    // The one-sided slacks default to the symmetric one
+    let slack_left = slack_left.unwrap_or(slack);
+    let slack_right = slack_right.unwrap_or(slack);
+
+    let order = OutputOrder::from_str(order).map_err(pyo3::exceptions::PyValueError::new_err)?;
+    let overlap_type =
+        OverlapType::from_str(overlap_type).map_err(pyo3::exceptions::PyValueError::new_err)?;
+    let relation = overlap_relation_or_contained(overlap_relation, contained)?;
+    let sweep_progress = PySweepProgress::new(progress, interruptible);
 
-    let result = if overlap_type == OverlapType::All && !contained {
-        // The common, super-optimized case
-        overlaps::sweep_line_overlaps(
+    let result = py.allow_threads(|| {
+        let result = chromsweep(
             chrs_slice,
             starts_slice,
             ends_slice,
             chrs_slice2,
             starts_slice2,
             ends_slice2,
-            slack,
+            slack_left,
+            slack_right,
+            strand_slice,
+            overlap_type,
+            relation,
+            threads,
+            sweep_progress.as_ref().map(|p| p as &dyn Progress),
+        )?;
+
+        let rows = sorts::pair_order(
+            order,
+            &result.0,
+            &result.1,
+            chrs_slice,
+            starts_slice,
+            ends_slice,
+            chrs_slice2,
+            starts_slice2,
+            ends_slice2,
+        );
+        Some(match rows {
+            Some(rows) => (
+                sorts::permute(&result.0, &rows),
+                sorts::permute(&result.1, &rows),
+            ),
+            None => result,
+        })
+    });
+    let Some(result) = result else {
+        return Err(sweep_progress.unwrap().into_error());
+    };
+
+    let res = Ok((
+        result.0.into_pyarray(py).unbind(),
+        result.1.into_pyarray(py).unbind(),
+    ));
+    res
+}
+
+/// `chromsweep_numpy` with the hits grouped by query: the set 2 rows hit by
+/// query `i` are `targets[offsets[i]..offsets[i + 1]]`, ascending.
+///
+/// The hits are grouped once the sweep is done, so the peak memory is that of
+/// `chromsweep_numpy`'s two index arrays plus the result, about 12 bytes per
+/// hit and 16 per query.
+#[pyfunction]
+#[pyo3(signature = (chrs, starts, ends, chrs2, starts2, ends2, slack=0, overlap_type="all", contained=false, slack_left=None, slack_right=None, strand_flags=None, overlap_relation=None, threads=1, progress=None, interruptible=false))]
+pub fn chromsweep_csr_numpy(
+    py: Python,
+    chrs: PyReadonlyArray1<u32>,
+    starts: PyReadonlyArray1<i64>,
+    ends: PyReadonlyArray1<i64>,
+    chrs2: PyReadonlyArray1<u32>,
+    starts2: PyReadonlyArray1<i64>,
+    ends2: PyReadonlyArray1<i64>,
+    slack: i64,
+    overlap_type: &str,
+    contained: bool,
+    slack_left: Option<i64>,
+    slack_right: Option<i64>,
+    strand_flags: Option<PyReadonlyArray1<bool>>,
+    overlap_relation: Option<&str>,
+    threads: usize,
+    progress: Option<Py<PyAny>>,
+    interruptible: bool,
+) -> PyResult<(Py<PyArray1<usize>>, Py<PyArray1<u32>>)> {
+    let chrs_slice = &owned(chrs);
+    let starts_slice = &owned(starts);
+    let ends_slice = &owned(ends);
+    let chrs_slice2 = &owned(chrs2);
+    let starts_slice2 = &owned(starts2);
+    let ends_slice2 = &owned(ends2);
+    let strand_flags = strand_flags.map(owned);
+    let strand_slice = strand_flags.as_deref();
+    check_strand_flags("strand_flags", strand_slice, chrs_slice.len())?;
+    let overlap_type =
+        OverlapType::from_str(overlap_type).map_err(pyo3::exceptions::PyValueError::new_err)?;
+    let relation = overlap_relation_or_contained(overlap_relation, contained)?;
+    let sweep_progress = PySweepProgress::new(progress, interruptible);
+
+    let result = py.allow_threads(|| {
+        let (idx, idx2) = chromsweep(
+            chrs_slice,
+            starts_slice,
+            ends_slice,
+            chrs_slice2,
+            starts_slice2,
+            ends_slice2,
+            slack_left.unwrap_or(slack),
+            slack_right.unwrap_or(slack),
+            strand_slice,
+            overlap_type,
+            relation,
+            threads,
+            sweep_progress.as_ref().map(|p| p as &dyn Progress),
+        )?;
+        Some(overlaps::overlaps_to_csr(chrs_slice.len(), &idx, &idx2))
+    });
+    let Some((offsets, targets)) = result else {
+        return Err(sweep_progress.unwrap().into_error());
+    };
+
+    Ok((
+        offsets.into_pyarray(py).unbind(),
+        targets.into_pyarray(py).unbind(),
+    ))
+}
+
+/// `chromsweep_numpy` for joins too large to hold at once: returns an
+/// iterator over `(idx, idx2)` arrays of at most `batch_size` hits each.
+/// Besides the batch, only the intervals open at the current position are
+/// kept. Hits come in the order the sweep finds them. Only the default
+/// `overlap_type="all"` and `overlap_relation="any"` are supported; the others
+/// raise `ValueError`.
+///
+/// `progress` and `interruptible` work as for `chromsweep_numpy`, checked while
+/// the batches are read: `events` counts two per interval read from either set
+/// and `chromosomes` is always 0. Once cancelled, the `next` call raises the
+/// exception and the iterator is exhausted.
+#[pyfunction]
+#[pyo3(signature = (chrs, starts, ends, chrs2, starts2, ends2, slack=0, overlap_type="all", contained=false, slack_left=None, slack_right=None, strand_flags=None, overlap_relation=None, batch_size=1_000_000, progress=None, interruptible=false))]
+pub fn chromsweep_batches_numpy(
+    py: Python,
+    chrs: PyReadonlyArray1<u32>,
+    starts: PyReadonlyArray1<i64>,
+    ends: PyReadonlyArray1<i64>,
+    chrs2: PyReadonlyArray1<u32>,
+    starts2: PyReadonlyArray1<i64>,
+    ends2: PyReadonlyArray1<i64>,
+    slack: i64,
+    overlap_type: &str,
+    contained: bool,
+    slack_left: Option<i64>,
+    slack_right: Option<i64>,
+    strand_flags: Option<PyReadonlyArray1<bool>>,
+    overlap_relation: Option<&str>,
+    batch_size: usize,
+    progress: Option<Py<PyAny>>,
+    interruptible: bool,
+) -> PyResult<PyOverlapBatches> {
+    let chrs_slice = &owned(chrs);
+    let starts_slice = &owned(starts);
+    let ends_slice = &owned(ends);
+    let chrs_slice2 = &owned(chrs2);
+    let starts_slice2 = &owned(starts2);
+    let ends_slice2 = &owned(ends2);
+    let strand_flags = strand_flags.map(owned);
+    let strand_slice = strand_flags.as_deref();
+    check_strand_flags("strand_flags", strand_slice, chrs_slice.len())?;
+
+    if batch_size == 0 {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "batch_size must be positive",
+        ));
+    }
+    let overlap_type =
+        OverlapType::from_str(overlap_type).map_err(pyo3::exceptions::PyValueError::new_err)?;
+    if overlap_type != OverlapType::All {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "Batches only support overlap_type=\"all\"",
+        ));
+    }
+    if overlap_relation_or_contained(overlap_relation, contained)? != OverlapRelation::Any {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "Batches only support overlap_relation=\"any\"",
+        ));
+    }
+
+    let progress = PySweepProgress::new(progress, interruptible).map(|progress| {
+        Arc::new(StreamProgress {
+            progress,
+            read: AtomicUsize::new(0),
+            cancelled: AtomicBool::new(false),
+        })
+    });
+    let pairs = py.allow_threads(|| {
+        let (starts1, ends1) = sorts::extend_intervals(
+            starts_slice,
+            ends_slice,
+            slack_left.unwrap_or(slack),
+            slack_right.unwrap_or(slack),
+            strand_slice,
+        );
+        streaming::overlaps(
+            ProgressStream {
+                intervals: sorted_stream(chrs_slice, &starts1, &ends1).into_iter(),
+                progress: progress.clone(),
+            },
+            ProgressStream {
+                intervals: sorted_stream(chrs_slice2, starts_slice2, ends_slice2).into_iter(),
+                progress: progress.clone(),
+            },
         )
+    });
+    Ok(PyOverlapBatches {
+        pairs,
+        batch_size,
+        progress,
+    })
+}
+
+/// `(chr, start, end, row)` of every interval, in the order
+/// `streaming::overlaps` expects.
+fn sorted_stream(chrs: &[u32], starts: &[i64], ends: &[i64]) -> Vec<(u32, i64, i64, u32)> {
+    let mut intervals: Vec<_> = (0..chrs.len())
+        .map(|i| (chrs[i], starts[i], ends[i], i as u32))
+        .collect();
+    sorts::sort_by_chr(&mut intervals, |i| i.0, |i| i.1);
+    intervals
+}
+
+/// One input of an `OverlapBatches`, reporting to the progress shared by both.
+struct ProgressStream {
+    intervals: std::vec::IntoIter<(u32, i64, i64, u32)>,
+    progress: Option<Arc<StreamProgress>>,
+}
+
+/// Counts the intervals read from both inputs of an `OverlapBatches`; once the
+/// progress cancels, both inputs end.
+struct StreamProgress {
+    progress: PySweepProgress,
+    read: AtomicUsize,
+    cancelled: AtomicBool,
+}
+
+impl Iterator for ProgressStream {
+    type Item = (u32, i64, i64, u32);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Some(state) = &self.progress else {
+            return self.intervals.next();
+        };
+        if state.cancelled.load(Ordering::Relaxed) {
+            return None;
+        }
+        let interval = self.intervals.next()?;
+        // Every interval has a start and an end event
+        let read = state.read.fetch_add(1, Ordering::Relaxed) + 1;
+        if read % (multiprocessing::PROGRESS_EVENTS / 2) == 0 && !state.progress.report(2 * read, 0)
+        {
+            state.cancelled.store(true, Ordering::Relaxed);
+            return None;
+        }
+        Some(interval)
+    }
+}
+
+type StreamedOverlaps = streaming::Overlaps<u32, u32, u32, ProgressStream, ProgressStream>;
+
+/// The iterator returned by `chromsweep_batches_numpy`.
+#[pyclass(name = "OverlapBatches")]
+pub struct PyOverlapBatches {
+    pairs: StreamedOverlaps,
+    batch_size: usize,
+    progress: Option<Arc<StreamProgress>>,
+}
+
+#[pymethods]
+impl PyOverlapBatches {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(
+        mut slf: PyRefMut<'_, Self>,
+        py: Python,
+    ) -> PyResult<Option<(Py<PyArray1<u32>>, Py<PyArray1<u32>>)>> {
+        let batches = &mut *slf;
+        let batch_size = batches.batch_size;
+        let pairs = py.allow_threads(|| {
+            batches
+                .pairs
+                .by_ref()
+                .take(batch_size)
+                .collect::<std::io::Result<Vec<(u32, u32)>>>()
+        });
+        // A cancelled sweep ends both inputs early; the batch so far is dropped
+        let progress = batches.progress.as_ref();
+        if let Some(error) = progress.and_then(|p| p.progress.take_error()) {
+            return Err(error);
+        }
+        let pairs = pairs?;
+        if pairs.is_empty() {
+            return Ok(None);
+        }
+        let (idx, idx2): (Vec<u32>, Vec<u32>) = pairs.into_iter().unzip();
+        Ok(Some((
+            idx.into_pyarray(py).unbind(),
+            idx2.into_pyarray(py).unbind(),
+        )))
+    }
+}
+
+/// `contained` is shorthand for the "within" relation, so passing both is an error.
+fn overlap_relation_or_contained(
+    overlap_relation: Option<&str>,
+    contained: bool,
+) -> PyResult<OverlapRelation> {
+    match overlap_relation {
+        Some(_) if contained => Err(pyo3::exceptions::PyValueError::new_err(
+            "Pass either overlap_relation or contained=True, not both",
+        )),
+        Some(r) => OverlapRelation::from_str(r).map_err(pyo3::exceptions::PyValueError::new_err),
+        None if contained => Ok(OverlapRelation::Within),
+        None => Ok(OverlapRelation::Any),
+    }
+}
+
+/// The (set 1 idx, set 2 idx) hits behind `chromsweep_numpy`, in sweep order.
+/// With `progress` the sweep runs in steps that report to it, and returns `None`
+/// when it is cancelled. Every thread count goes through the same coordinates
+/// and sweep, one partition at a time, and empty intervals count as the position
+/// they sit at, so the hits do not depend on `threads`.
+fn chromsweep(
+    chrs_slice: &[u32],
+    starts_slice: &[i64],
+    ends_slice: &[i64],
+    chrs_slice2: &[u32],
+    starts_slice2: &[i64],
+    ends_slice2: &[i64],
+    slack_left: i64,
+    slack_right: i64,
+    strand_slice: Option<&[bool]>,
+    overlap_type: OverlapType,
+    relation: OverlapRelation,
+    threads: usize,
+    progress: Option<&dyn Progress>,
+) -> Option<(Vec<u32>, Vec<u32>)> {
+    let invert = overlap_type == OverlapType::Last;
+    // The "last" path sweeps in negated coordinates
+    let relation = if invert {
+        relation.mirrored()
     } else {
-        if !contained {
-            let (sorted_starts, sorted_ends) = overlaps::compute_sorted_events(
-                chrs_slice,
-                starts_slice,
-                ends_slice,
-                slack,
-                invert,
-            );
-            let (sorted_starts2, sorted_ends2) =
-                overlaps::compute_sorted_events(chrs_slice2, starts_slice2, ends_slice2, 0, invert);
+        relation
+    };
+
+    let (starts1, ends1) = overlaps::overlap_coordinates(
+        starts_slice,
+        ends_slice,
+        slack_left,
+        slack_right,
+        strand_slice,
+        invert,
+    );
+    let (starts2, ends2) =
+        overlaps::overlap_coordinates(starts_slice2, ends_slice2, 0, 0, None, invert);
+    let sorted_starts = sorts::build_sorted_min_events(chrs_slice, &starts1, threads);
+    let sorted_ends = sorts::build_sorted_min_events(chrs_slice, &ends1, threads);
+    let sorted_starts2 = sorts::build_sorted_min_events(chrs_slice2, &starts2, threads);
+    let sorted_ends2 = sorts::build_sorted_min_events(chrs_slice2, &ends2, threads);
 
-            let mut pairs = overlaps::sweep_line_overlaps_overlap_pair(
+    let sweep = |p: &PartitionIndex| {
+        let (sorted_starts, sorted_ends) = (
+            &sorted_starts[p.start1..p.end1],
+            &sorted_ends[p.start1..p.end1],
+        );
+        let (sorted_starts2, sorted_ends2) = (
+            &sorted_starts2[p.start2..p.end2],
+            &sorted_ends2[p.start2..p.end2],
+        );
+        if relation == OverlapRelation::Any {
+            overlaps::sweep_line_overlaps_overlap_pair(
+                sorted_starts,
+                sorted_ends,
+                sorted_starts2,
+                sorted_ends2,
+            )
+        } else {
+            overlaps::sweep_line_overlaps_relation_sorted(
+                sorted_starts,
+                sorted_ends,
+                sorted_starts2,
+                sorted_ends2,
+                &starts1,
+                &ends1,
+                &starts2,
+                &ends2,
+                relation,
+            )
+        }
+    };
+
+    // Partitions come back in sweep order, so the pairs are in the order of a
+    // single sweep
+    let mut pairs = match progress {
+        Some(progress) => multiprocessing::run_with_progress(
+            &sorted_starts,
+            &sorted_ends,
+            &sorted_starts2,
+            &sorted_ends2,
+            threads,
+            sweep,
+            progress,
+        )?
+        .concat(),
+        None if threads <= 1 => sweep(&PartitionIndex {
+            start1: 0,
+            end1: sorted_starts.len(),
+            start2: 0,
+            end2: sorted_starts2.len(),
+        }),
+        None => {
+            let partitions = multiprocessing::partition_at_coverage_gaps(
                 &sorted_starts,
                 &sorted_ends,
                 &sorted_starts2,
                 &sorted_ends2,
+                threads,
             );
-            keep_first_by_idx(&mut pairs);
-            pairs.into_iter().map(|pair| (pair.idx, pair.idx2)).unzip()
-        } else {
-            let maxevents = overlaps::compute_sorted_maxevents(
-                chrs_slice,
-                starts_slice,
-                ends_slice,
-                chrs_slice2,
-                starts_slice2,
-                ends_slice2,
-                slack,
-                invert,
-            );
-            let mut pairs = overlaps::sweep_line_overlaps_containment(maxevents);
-            if overlap_type == OverlapType::All {
-                pairs.into_iter().map(|pair| (pair.idx, pair.idx2)).unzip()
-            } else {
-                keep_first_by_idx(&mut pairs);
-                pairs.into_iter().map(|pair| (pair.idx, pair.idx2)).unzip()
+            multiprocessing::run_on_partitions(&partitions, threads, sweep).concat()
+        }
+    };
+    if overlap_type != OverlapType::All {
+        keep_first_by_idx(&mut pairs);
+    }
+    Some(pairs.into_iter().map(|pair| (pair.idx, pair.idx2)).unzip())
+}
+
+/// Checks for Ctrl-C and other Python signals between the steps of a sweep and
+/// calls the optional `progress(events, chromosomes)` callback. An exception from
+/// either cancels the sweep; it is kept to be raised once the GIL is back.
+struct PySweepProgress {
+    callback: Option<Py<PyAny>>,
+    error: Mutex<Option<PyErr>>,
+}
+
+impl PySweepProgress {
+    /// `None` when the caller asked for neither a callback nor interruption, so
+    /// that the sweep can take its fastest path.
+    fn new(callback: Option<Py<PyAny>>, interruptible: bool) -> Option<Self> {
+        (callback.is_some() || interruptible).then(|| PySweepProgress {
+            callback,
+            error: Mutex::new(None),
+        })
+    }
+
+    /// The exception that cancelled the sweep.
+    fn into_error(self) -> PyErr {
+        self.error
+            .into_inner()
+            .unwrap()
+            .expect("the sweep was cancelled without an exception")
+    }
+
+    /// The exception that cancelled the sweep, if it did and it was not taken yet.
+    fn take_error(&self) -> Option<PyErr> {
+        self.error.lock().unwrap().take()
+    }
+}
+
+impl Progress for PySweepProgress {
+    fn report(&self, events: usize, chromosomes: usize) -> bool {
+        let result = Python::with_gil(|py| -> PyResult<()> {
+            py.check_signals()?;
+            if let Some(callback) = &self.callback {
+                callback.call1(py, (events, chromosomes))?;
+            }
+            Ok(())
+        });
+        match result {
+            Ok(()) => true,
+            Err(e) => {
+                *self.error.lock().unwrap() = Some(e);
+                false
             }
         }
+    }
+}
+
+/// With `out`, the counts are copied into it once they are all computed.
+///
+/// `progress` and `interruptible` work as for `chromsweep_numpy`, checked between
+/// chunks of a few million queries: `events` counts two per query counted and
+/// `chromosomes` is always 0. A cancelled count leaves `out` untouched.
+#[pyfunction]
+#[pyo3(signature = (chrs, starts, ends, chrs2, starts2, ends2, slack=0, threads=1, out=None, progress=None, interruptible=false))]
+pub fn count_overlaps_numpy(
+    py: Python,
+    chrs: PyReadonlyArray1<u32>,
+    starts: PyReadonlyArray1<i64>,
+    ends: PyReadonlyArray1<i64>,
+    chrs2: PyReadonlyArray1<u32>,
+    starts2: PyReadonlyArray1<i64>,
+    ends2: PyReadonlyArray1<i64>,
+    slack: i64,
+    threads: usize,
+    out: Option<Bound<'_, PyArray1<u32>>>,
+    progress: Option<Py<PyAny>>,
+    interruptible: bool,
+) -> PyResult<Py<PyArray1<u32>>> {
+    let chrs_slice = &owned(chrs);
+    let starts_slice = &owned(starts);
+    let ends_slice = &owned(ends);
+    let chrs_slice2 = &owned(chrs2);
+    let starts_slice2 = &owned(starts2);
+    let ends_slice2 = &owned(ends2);
+
+    let sweep_progress = PySweepProgress::new(progress, interruptible);
+
+    check_out(out.as_ref(), chrs_slice.len())?;
+    let counts = py.allow_threads(|| {
+        let mut counts = vec![0; chrs_slice.len()];
+        overlaps::count_overlaps_with_progress_into(
+            chrs_slice,
+            starts_slice,
+            ends_slice,
+            chrs_slice2,
+            starts_slice2,
+            ends_slice2,
+            slack,
+            threads,
+            &mut counts,
+            sweep_progress.as_ref().map(|p| p as &dyn Progress),
+        )
+        .then_some(counts)
+    });
+    let Some(counts) = counts else {
+        return Err(sweep_progress.unwrap().into_error());
     };
+    Ok(into_out(py, out, counts)?.unbind())
+}
 
-    let res = Ok((
-        result.0.into_pyarray(py).to_owned().into(),
-        result.1.into_pyarray(py).to_owned().into(),
-    ));
-    res
+/// Checks that the `out` array the caller passed, if any, is contiguous and
+/// holds `len` values, before the result is computed.
+fn check_out<T: Element>(out: Option<&Bound<'_, PyArray1<T>>>, len: usize) -> PyResult<()> {
+    match out {
+        Some(out) if !out.is_contiguous() => Err(pyo3::exceptions::PyTypeError::new_err(
+            "out must be contiguous",
+        )),
+        Some(out) if out.len() != len => Err(pyo3::exceptions::PyValueError::new_err(format!(
+            "out has length {}, expected {}",
+            out.len(),
+            len
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// `values` copied into `out` when the caller passed one, otherwise handed over
+/// as a new array. The values are computed with the GIL released, when Python
+/// code may use `out`, so it is only written here, in one go under the GIL.
+fn into_out<'py, T: Element + Copy>(
+    py: Python<'py>,
+    out: Option<Bound<'py, PyArray1<T>>>,
+    values: Vec<T>,
+) -> PyResult<Bound<'py, PyArray1<T>>> {
+    match out {
+        Some(out) => {
+            out.try_readwrite()?
+                .as_slice_mut()?
+                .copy_from_slice(&values);
+            Ok(out)
+        }
+        None => Ok(values.into_pyarray(py)),
+    }
+}
+
+/// A copy of `array` for the sweeps to read with the GIL released. Once the GIL
+/// is released Python code can write to the array, so it is never read in place.
+/// The array may be strided, and its borrow ends here, so an `out` array may be
+/// one of the inputs.
+fn owned<T: Element + Copy>(array: PyReadonlyArray1<T>) -> Vec<T> {
+    array.as_array().to_vec()
+}
+
+/// Checks that the optional per-interval strand flags have one entry per interval.
+fn check_strand_flags(name: &str, strand_flags: Option<&[bool]>, len: usize) -> PyResult<()> {
+    match strand_flags {
+        Some(flags) if flags.len() != len => Err(pyo3::exceptions::PyValueError::new_err(format!(
+            "{} has length {}, expected {}",
+            name,
+            flags.len(),
+            len
+        ))),
+        _ => Ok(()),
+    }
 }
 
 fn keep_first_by_idx(pairs: &mut Vec<OverlapPair>) {
@@ -133,8 +691,11 @@ fn keep_first_by_idx(pairs: &mut Vec<OverlapPair>) {
     pairs.retain(|pair| seen_idx.insert(pair.idx));
 }
 
+/// `progress` and `interruptible` work as for `chromsweep_numpy`, checked between
+/// chunks of a few million queries while the neighbours are searched: `events`
+/// counts two per query searched and `chromosomes` those finished.
 #[pyfunction]
-#[pyo3(signature = (*, chrs, starts, ends, chrs2, starts2, ends2, slack=0, k=1, include_overlaps=true, direction="any"))]
+#[pyo3(signature = (*, chrs, starts, ends, chrs2, starts2, ends2, slack=0, k=1, include_overlaps=true, direction="any", max_distance=None, distance_metric="edge", strand_flags=None, strand_flags2=None, k_by_intervals=false, order="input", threads=1, progress=None, interruptible=false))]
 pub fn nearest_numpy(
     py: Python,
     chrs: PyReadonlyArray1<u32>,
@@ -147,34 +708,227 @@ pub fn nearest_numpy(
     k: usize,
     include_overlaps: bool,
     direction: &str,
+    max_distance: Option<i64>,
+    distance_metric: &str,
+    strand_flags: Option<PyReadonlyArray1<bool>>,
+    strand_flags2: Option<PyReadonlyArray1<bool>>,
+    k_by_intervals: bool,
+    order: &str,
+    threads: usize,
+    progress: Option<Py<PyAny>>,
+    interruptible: bool,
 ) -> PyResult<(Py<PyArray1<u32>>, Py<PyArray1<u32>>, Py<PyArray1<i64>>)> {
-    let chrs_slice = chrs.as_slice()?;
-    let starts_slice = starts.as_slice()?;
-    let ends_slice = ends.as_slice()?;
-    let chrs_slice2 = chrs2.as_slice()?;
-    let starts_slice2 = starts2.as_slice()?;
-    let ends_slice2 = ends2.as_slice()?;
-
-    let result = nearest(
-        chrs_slice,
-        starts_slice,
-        ends_slice,
-        chrs_slice2,
-        starts_slice2,
-        ends_slice2,
-        slack,
-        k,
-        include_overlaps,
-        direction,
-    );
+    let chrs_slice = &owned(chrs);
+    let starts_slice = &owned(starts);
+    let ends_slice = &owned(ends);
+    let chrs_slice2 = &owned(chrs2);
+    let starts_slice2 = &owned(starts2);
+    let ends_slice2 = &owned(ends2);
+    let strand_flags = strand_flags.map(owned);
+    let strand_slice = strand_flags.as_deref();
+    let strand_flags2 = strand_flags2.map(owned);
+    let strand_slice2 = strand_flags2.as_deref();
+    check_strand_flags("strand_flags", strand_slice, chrs_slice.len())?;
+    check_strand_flags("strand_flags2", strand_slice2, chrs_slice2.len())?;
+
+    let order = OutputOrder::from_str(order).map_err(pyo3::exceptions::PyValueError::new_err)?;
+    let sweep_progress = PySweepProgress::new(progress, interruptible);
+
+    let result = py
+        .allow_threads(|| -> Result<_, &'static str> {
+            let options = NearestOptions {
+                slack,
+                k,
+                include_overlaps,
+                direction,
+                max_distance,
+                distance_metric,
+                strand_flags: strand_slice,
+                strand_flags2: strand_slice2,
+                k_by_intervals,
+                threads,
+            };
+            let result = nearest_with_progress(
+                chrs_slice,
+                starts_slice,
+                ends_slice,
+                chrs_slice2,
+                starts_slice2,
+                ends_slice2,
+                &options,
+                sweep_progress.as_ref().map(|p| p as &dyn Progress),
+            )?;
+            let Some(result) = result else {
+                return Ok(None);
+            };
+            let rows = sorts::pair_order(
+                order,
+                &result.0,
+                &result.1,
+                chrs_slice,
+                starts_slice,
+                ends_slice,
+                chrs_slice2,
+                starts_slice2,
+                ends_slice2,
+            );
+            Ok(Some(match rows {
+                Some(rows) => (
+                    sorts::permute(&result.0, &rows),
+                    sorts::permute(&result.1, &rows),
+                    sorts::permute(&result.2, &rows),
+                ),
+                None => result,
+            }))
+        })
+        .map_err(pyo3::exceptions::PyValueError::new_err)?;
+    let Some(result) = result else {
+        return Err(sweep_progress.unwrap().into_error());
+    };
     let res = Ok((
-        result.0.into_pyarray(py).to_owned().into(),
-        result.1.into_pyarray(py).to_owned().into(),
-        result.2.into_pyarray(py).to_owned().into(),
+        result.0.into_pyarray(py).unbind(),
+        result.1.into_pyarray(py).unbind(),
+        result.2.into_pyarray(py).unbind(),
     ));
     res
 }
 
+#[pyfunction]
+#[pyo3(signature = (*, chrs, positions, chrs2, starts2, ends2, k=1, include_overlaps=true, direction="any", max_distance=None, distance_metric="edge", strand_flags2=None, k_by_intervals=false))]
+pub fn nearest_points_numpy(
+    py: Python,
+    chrs: PyReadonlyArray1<u32>,
+    positions: PyReadonlyArray1<i64>,
+    chrs2: PyReadonlyArray1<u32>,
+    starts2: PyReadonlyArray1<i64>,
+    ends2: PyReadonlyArray1<i64>,
+    k: usize,
+    include_overlaps: bool,
+    direction: &str,
+    max_distance: Option<i64>,
+    distance_metric: &str,
+    strand_flags2: Option<PyReadonlyArray1<bool>>,
+    k_by_intervals: bool,
+) -> PyResult<(Py<PyArray1<u32>>, Py<PyArray1<u32>>, Py<PyArray1<i64>>)> {
+    let chrs_slice = &owned(chrs);
+    let positions_slice = &owned(positions);
+    let chrs_slice2 = &owned(chrs2);
+    let starts_slice2 = &owned(starts2);
+    let ends_slice2 = &owned(ends2);
+    let strand_flags2 = strand_flags2.map(owned);
+    let strand_slice2 = strand_flags2.as_deref();
+    check_strand_flags("strand_flags2", strand_slice2, chrs_slice2.len())?;
+
+    let result = py
+        .allow_threads(|| {
+            let options = NearestOptions {
+                k,
+                include_overlaps,
+                direction,
+                max_distance,
+                distance_metric,
+                strand_flags2: strand_slice2,
+                k_by_intervals,
+                ..NearestOptions::default()
+            };
+            nearest_points(
+                chrs_slice,
+                positions_slice,
+                chrs_slice2,
+                starts_slice2,
+                ends_slice2,
+                &options,
+            )
+        })
+        .map_err(pyo3::exceptions::PyValueError::new_err)?;
+    Ok((
+        result.0.into_pyarray(py).unbind(),
+        result.1.into_pyarray(py).unbind(),
+        result.2.into_pyarray(py).unbind(),
+    ))
+}
+
+#[pyfunction]
+#[pyo3(signature = (chrs, positions, chrs2, starts2, ends2, slack=0))]
+pub fn points_in_intervals_numpy(
+    py: Python,
+    chrs: PyReadonlyArray1<u32>,
+    positions: PyReadonlyArray1<i64>,
+    chrs2: PyReadonlyArray1<u32>,
+    starts2: PyReadonlyArray1<i64>,
+    ends2: PyReadonlyArray1<i64>,
+    slack: i64,
+) -> PyResult<(Py<PyArray1<u32>>, Py<PyArray1<u32>>)> {
+    let chrs_slice = &owned(chrs);
+    let positions_slice = &owned(positions);
+    let chrs_slice2 = &owned(chrs2);
+    let starts_slice2 = &owned(starts2);
+    let ends_slice2 = &owned(ends2);
+
+    let (idx, idx2): (Vec<u32>, Vec<u32>) = py.allow_threads(|| {
+        let pairs = points_in_intervals(
+            chrs_slice,
+            positions_slice,
+            chrs_slice2,
+            starts_slice2,
+            ends_slice2,
+            slack,
+        );
+        pairs.into_iter().map(|pair| (pair.idx, pair.idx2)).unzip()
+    });
+    Ok((
+        idx.into_pyarray(py).unbind(),
+        idx2.into_pyarray(py).unbind(),
+    ))
+}
+
+#[pyfunction]
+#[pyo3(signature = (*, chrs, starts, ends, chrs2, starts2, ends2, slack=0, max_distance=None))]
+pub fn flanking_neighbours_numpy(
+    py: Python,
+    chrs: PyReadonlyArray1<u32>,
+    starts: PyReadonlyArray1<i64>,
+    ends: PyReadonlyArray1<i64>,
+    chrs2: PyReadonlyArray1<u32>,
+    starts2: PyReadonlyArray1<i64>,
+    ends2: PyReadonlyArray1<i64>,
+    slack: i64,
+    max_distance: Option<i64>,
+) -> PyResult<(
+    Py<PyArray1<i64>>,
+    Py<PyArray1<i64>>,
+    Py<PyArray1<i64>>,
+    Py<PyArray1<i64>>,
+    Py<PyArray1<bool>>,
+)> {
+    let chrs_slice = &owned(chrs);
+    let starts_slice = &owned(starts);
+    let ends_slice = &owned(ends);
+    let chrs_slice2 = &owned(chrs2);
+    let starts_slice2 = &owned(starts2);
+    let ends_slice2 = &owned(ends2);
+
+    let (left_idx, left_distance, right_idx, right_distance, overlaps) = py.allow_threads(|| {
+        flanking_neighbours(
+            chrs_slice,
+            starts_slice,
+            ends_slice,
+            chrs_slice2,
+            starts_slice2,
+            ends_slice2,
+            slack,
+            max_distance,
+        )
+    });
+    Ok((
+        left_idx.into_pyarray(py).unbind(),
+        left_distance.into_pyarray(py).unbind(),
+        right_idx.into_pyarray(py).unbind(),
+        right_distance.into_pyarray(py).unbind(),
+        overlaps.into_pyarray(py).unbind(),
+    ))
+}
+
 #[pyfunction]
 pub fn subtract_numpy(
     py: Python,
@@ -185,41 +939,89 @@ pub fn subtract_numpy(
     starts2: PyReadonlyArray1<i64>,
     ends2: PyReadonlyArray1<i64>,
 ) -> PyResult<(Py<PyArray1<usize>>, Py<PyArray1<i64>>, Py<PyArray1<i64>>)> {
-    let chrs_slice = chrs.as_slice()?;
-    let starts_slice = starts.as_slice()?;
-    let ends_slice = ends.as_slice()?;
-    let chrs_slice2 = chrs2.as_slice()?;
-    let starts_slice2 = starts2.as_slice()?;
-    let ends_slice2 = ends2.as_slice()?;
-
-    let result = sweep_line_subtract(
-        chrs_slice,
-        starts_slice,
-        ends_slice,
-        chrs_slice2,
-        starts_slice2,
-        ends_slice2,
-    );
+    let chrs_slice = &owned(chrs);
+    let starts_slice = &owned(starts);
+    let ends_slice = &owned(ends);
+    let chrs_slice2 = &owned(chrs2);
+    let starts_slice2 = &owned(starts2);
+    let ends_slice2 = &owned(ends2);
+
+    let result = py.allow_threads(|| {
+        sweep_line_subtract(
+            chrs_slice,
+            starts_slice,
+            ends_slice,
+            chrs_slice2,
+            starts_slice2,
+            ends_slice2,
+        )
+    });
     Ok((
-        result.0.into_pyarray(py).to_owned().into(),
-        result.1.into_pyarray(py).to_owned().into(),
-        result.2.into_pyarray(py).to_owned().into(),
+        result.0.into_pyarray(py).unbind(),
+        result.1.into_pyarray(py).unbind(),
+        result.2.into_pyarray(py).unbind(),
     ))
 }
 
 #[pyfunction]
+#[pyo3(signature = (chrs, starts, ends, out=None))]
 pub fn sort_intervals_numpy(
     chrs: PyReadonlyArray1<i64>,
     starts: PyReadonlyArray1<i64>,
     ends: PyReadonlyArray1<i64>,
+    out: Option<Bound<'_, PyArray1<usize>>>,
     py: Python,
 ) -> PyResult<Py<PyArray1<usize>>> {
-    let chrs_slice = chrs.as_slice()?;
-    let starts_slice = starts.as_slice()?;
-    let ends_slice = ends.as_slice()?;
+    let chrs_slice = &owned(chrs);
+    let starts_slice = &owned(starts);
+    let ends_slice = &owned(ends);
+
+    check_out(out.as_ref(), chrs_slice.len())?;
+    let indexes = py.allow_threads(|| {
+        let mut indexes = vec![0; chrs_slice.len()];
+        sorts::sort_order_idx_into(chrs_slice, starts_slice, ends_slice, &mut indexes);
+        indexes
+    });
+    Ok(into_out(py, out, indexes)?.unbind())
+}
+
+/// Covered positions per bin of `bin_size` along each chromosome, counting a
+/// position once per interval covering it. `chrs` index `chrom_lengths`, and the
+/// bins of all chromosomes follow one another in that order. With `out`, the
+/// result is copied into it.
+#[pyfunction]
+#[pyo3(signature = (chrs, starts, ends, chrom_lengths, bin_size, out=None))]
+pub fn coverage_per_bin_numpy(
+    py: Python,
+    chrs: PyReadonlyArray1<u32>,
+    starts: PyReadonlyArray1<i64>,
+    ends: PyReadonlyArray1<i64>,
+    chrom_lengths: PyReadonlyArray1<i64>,
+    bin_size: i64,
+    out: Option<Bound<'_, PyArray1<i64>>>,
+) -> PyResult<Py<PyArray1<i64>>> {
+    let chrs_slice = &owned(chrs);
+    let starts_slice = &owned(starts);
+    let ends_slice = &owned(ends);
+    let lengths_slice = &owned(chrom_lengths);
 
-    let indexes = sorts::sort_order_idx(chrs_slice, starts_slice, ends_slice);
-    Ok(indexes.into_pyarray(py).to_owned().into())
+    let bins = coverage::bin_count(lengths_slice, bin_size)
+        .map_err(pyo3::exceptions::PyValueError::new_err)?;
+    check_out(out.as_ref(), bins)?;
+    let result = py.allow_threads(|| {
+        let mut result = vec![0; bins];
+        coverage::coverage_per_bin_into(
+            chrs_slice,
+            starts_slice,
+            ends_slice,
+            lengths_slice,
+            bin_size,
+            &mut result,
+        )
+        .map(|()| result)
+    });
+    let result = result.map_err(pyo3::exceptions::PyValueError::new_err)?;
+    Ok(into_out(py, out, result)?.unbind())
 }
 
 // #[pyfunction]
@@ -339,24 +1141,47 @@ pub fn sort_intervals_numpy(
 //     ))
 // }
 
+/// With `out=(cluster_ids, indices)`, the result is copied into the two arrays.
 #[pyfunction]
-#[pyo3(signature = (chrs, starts, ends, slack=0))]
+#[pyo3(signature = (chrs, starts, ends, slack=0, order="input", out=None))]
 pub fn cluster_numpy(
     chrs: PyReadonlyArray1<i64>,
     starts: PyReadonlyArray1<i64>,
     ends: PyReadonlyArray1<i64>,
     slack: i64,
+    order: &str,
+    out: Option<(Bound<'_, PyArray1<i64>>, Bound<'_, PyArray1<usize>>)>,
     py: Python,
 ) -> PyResult<(Py<PyArray1<i64>>, Py<PyArray1<usize>>)> {
-    let (cluster_ids, indices) = sweep_line_cluster(
-        chrs.as_slice()?,
-        starts.as_slice()?,
-        ends.as_slice()?,
-        slack,
-    );
+    let chrs_slice = &owned(chrs);
+    let starts_slice = &owned(starts);
+    let ends_slice = &owned(ends);
+
+    let order = OutputOrder::from_str(order).map_err(pyo3::exceptions::PyValueError::new_err)?;
+
+    let (out_cluster_ids, out_indices) = out.unzip();
+    check_out(out_cluster_ids.as_ref(), chrs_slice.len())?;
+    check_out(out_indices.as_ref(), chrs_slice.len())?;
+    let (cluster_ids, indices) = py.allow_threads(|| {
+        let mut cluster_ids = vec![0; chrs_slice.len()];
+        let mut indices = vec![0; chrs_slice.len()];
+        sweep_line_cluster_into(
+            chrs_slice,
+            starts_slice,
+            ends_slice,
+            slack,
+            &mut cluster_ids,
+            &mut indices,
+        );
+        if let Some(rows) = sorts::row_order(order, &indices, chrs_slice, starts_slice, ends_slice)
+        {
+            sorts::permute_pair_in_place(&mut cluster_ids, &mut indices, rows);
+        }
+        (cluster_ids, indices)
+    });
     Ok((
-        cluster_ids.into_pyarray(py).to_owned().into(),
-        indices.into_pyarray(py).to_owned().into(),
+        into_out(py, out_cluster_ids, cluster_ids)?.unbind(),
+        into_out(py, out_indices, indices)?.unbind(),
     ))
 }
 
@@ -374,13 +1199,17 @@ pub fn tile_numpy(
     Py<PyArray1<i64>>,
     Py<PyArray1<f64>>,
 )> {
+    let starts_slice = &owned(starts);
+    let ends_slice = &owned(ends);
+    let strand_slice = &owned(negative_strand);
+
     let (starts, ends, indices, overlap_fraction) =
-        tile(starts.as_slice()?, ends.as_slice()?, negative_strand.as_slice()?, tile_size);
+        py.allow_threads(|| tile(starts_slice, ends_slice, strand_slice, tile_size));
     Ok((
-        indices.into_pyarray(py).to_owned().into(),
-        starts.into_pyarray(py).to_owned().into(),
-        ends.into_pyarray(py).to_owned().into(),
-        overlap_fraction.into_pyarray(py).to_owned().into(),
+        indices.into_pyarray(py).unbind(),
+        starts.into_pyarray(py).unbind(),
+        ends.into_pyarray(py).unbind(),
+        overlap_fraction.into_pyarray(py).unbind(),
     ))
 }
 
@@ -393,11 +1222,16 @@ pub fn window_numpy(
     window_size: i64,
     py: Python,
 ) -> PyResult<(Py<PyArray1<usize>>, Py<PyArray1<i64>>, Py<PyArray1<i64>>)> {
-    let (starts, ends, indices) = window(starts.as_slice()?, ends.as_slice()?, negative_strand.as_slice()?, window_size);
+    let starts_slice = &owned(starts);
+    let ends_slice = &owned(ends);
+    let strand_slice = &owned(negative_strand);
+
+    let (starts, ends, indices) =
+        py.allow_threads(|| window(starts_slice, ends_slice, strand_slice, window_size));
     Ok((
-        indices.into_pyarray(py).to_owned().into(),
-        starts.into_pyarray(py).to_owned().into(),
-        ends.into_pyarray(py).to_owned().into(),
+        indices.into_pyarray(py).unbind(),
+        starts.into_pyarray(py).unbind(),
+        ends.into_pyarray(py).unbind(),
     ))
 }
 
@@ -415,17 +1249,17 @@ pub fn merge_numpy(
     Py<PyArray1<i64>>,
     Py<PyArray1<i64>>,
 )> {
-    let (indices, starts, ends, counts) = sweep_line_merge(
-        chrs.as_slice()?,
-        starts.as_slice()?,
-        ends.as_slice()?,
-        slack,
-    );
+    let chrs_slice = &owned(chrs);
+    let starts_slice = &owned(starts);
+    let ends_slice = &owned(ends);
+
+    let (indices, starts, ends, counts) =
+        py.allow_threads(|| sweep_line_merge(chrs_slice, starts_slice, ends_slice, slack));
     Ok((
-        indices.into_pyarray(py).to_owned().into(),
-        starts.into_pyarray(py).to_owned().into(),
-        ends.into_pyarray(py).to_owned().into(),
-        counts.into_pyarray(py).to_owned().into(),
+        indices.into_pyarray(py).unbind(),
+        starts.into_pyarray(py).unbind(),
+        ends.into_pyarray(py).unbind(),
+        counts.into_pyarray(py).unbind(),
     ))
 }
 
@@ -439,17 +1273,16 @@ pub fn split_numpy(
     between: bool,
     py: Python,
 ) -> PyResult<(Py<PyArray1<usize>>, Py<PyArray1<i64>>, Py<PyArray1<i64>>)> {
-    let (indices, starts, ends) = sweep_line_split(
-        chrs.as_slice()?,
-        starts.as_slice()?,
-        ends.as_slice()?,
-        slack,
-        between,
-    );
+    let chrs_slice = &owned(chrs);
+    let starts_slice = &owned(starts);
+    let ends_slice = &owned(ends);
+
+    let (indices, starts, ends) =
+        py.allow_threads(|| sweep_line_split(chrs_slice, starts_slice, ends_slice, slack, between));
     Ok((
-        indices.into_pyarray(py).to_owned().into(),
-        starts.into_pyarray(py).to_owned().into(),
-        ends.into_pyarray(py).to_owned().into(),
+        indices.into_pyarray(py).unbind(),
+        starts.into_pyarray(py).unbind(),
+        ends.into_pyarray(py).unbind(),
     ))
 }
 
@@ -465,19 +1298,26 @@ pub fn spliced_subsequence_numpy(
     force_plus_strand: bool,
     py: Python,
 ) -> PyResult<(Py<PyArray1<usize>>, Py<PyArray1<i64>>, Py<PyArray1<i64>>)> {
-    let (outidx, outstarts, outends) = spliced_subseq(
-        chrs.as_slice()?,
-        starts.as_slice()?,
-        ends.as_slice()?,
-        strand_flags.as_slice()?,
-        start,
-        end,
-        force_plus_strand,
-    );
+    let chrs_slice = &owned(chrs);
+    let starts_slice = &owned(starts);
+    let ends_slice = &owned(ends);
+    let strand_slice = &owned(strand_flags);
+
+    let (outidx, outstarts, outends) = py.allow_threads(|| {
+        spliced_subseq(
+            chrs_slice,
+            starts_slice,
+            ends_slice,
+            strand_slice,
+            start,
+            end,
+            force_plus_strand,
+        )
+    });
     Ok((
-        outidx.into_pyarray(py).to_owned().into(),
-        outstarts.into_pyarray(py).to_owned().into(),
-        outends.into_pyarray(py).to_owned().into(),
+        outidx.into_pyarray(py).unbind(),
+        outstarts.into_pyarray(py).unbind(),
+        outends.into_pyarray(py).unbind(),
     ))
 }
 
@@ -515,23 +1355,25 @@ pub fn complement_overlaps_numpy(
     ends2: PyReadonlyArray1<i64>,
     slack: i64,
 ) -> PyResult<Py<PyArray1<usize>>> {
-    let chrs_slice = chrs.as_slice()?;
-    let starts_slice = starts.as_slice()?;
-    let ends_slice = ends.as_slice()?;
-    let chrs_slice2 = chrs2.as_slice()?;
-    let starts_slice2 = starts2.as_slice()?;
-    let ends_slice2 = ends2.as_slice()?;
-
-    let result = sweep_line_non_overlaps(
-        chrs_slice,
-        starts_slice,
-        ends_slice,
-        chrs_slice2,
-        starts_slice2,
-        ends_slice2,
-        slack,
-    );
-    Ok(result.into_pyarray(py).to_owned().into())
+    let chrs_slice = &owned(chrs);
+    let starts_slice = &owned(starts);
+    let ends_slice = &owned(ends);
+    let chrs_slice2 = &owned(chrs2);
+    let starts_slice2 = &owned(starts2);
+    let ends_slice2 = &owned(ends2);
+
+    let result = py.allow_threads(|| {
+        sweep_line_non_overlaps(
+            chrs_slice,
+            starts_slice,
+            ends_slice,
+            chrs_slice2,
+            starts_slice2,
+            ends_slice2,
+            slack,
+        )
+    });
+    Ok(result.into_pyarray(py).unbind())
 }
 
 #[pyfunction]
@@ -550,12 +1392,12 @@ pub fn complement_numpy(
     Py<PyArray1<i64>>,
     Py<PyArray1<usize>>,
 )> {
-    let chrs_slice = chrs.as_slice()?;
-    let starts_slice = starts.as_slice()?;
-    let ends_slice = ends.as_slice()?;
+    let chrs_slice = &owned(chrs);
+    let starts_slice = &owned(starts);
+    let ends_slice = &owned(ends);
 
-    let keys = chrom_len_ids.as_slice()?;
-    let vals = chrom_lens.as_slice()?;
+    let keys = &owned(chrom_len_ids);
+    let vals = &owned(chrom_lens);
 
     if keys.len() != vals.len() {
         return Err(pyo3::exceptions::PyValueError::new_err(
@@ -567,19 +1409,21 @@ pub fn complement_numpy(
         lens_map.insert(k, v);
     }
 
-    let (outchrs, outstarts, outends, outidxs) = sweep_line_complement(
-        chrs_slice,
-        starts_slice,
-        ends_slice,
-        slack,
-        &lens_map,
-        include_first_interval,
-    );
+    let (outchrs, outstarts, outends, outidxs) = py.allow_threads(|| {
+        sweep_line_complement(
+            chrs_slice,
+            starts_slice,
+            ends_slice,
+            slack,
+            &lens_map,
+            include_first_interval,
+        )
+    });
     Ok((
-        outchrs.into_pyarray(py).to_owned().into(),
-        outstarts.into_pyarray(py).to_owned().into(),
-        outends.into_pyarray(py).to_owned().into(),
-        outidxs.into_pyarray(py).to_owned().into(),
+        outchrs.into_pyarray(py).unbind(),
+        outstarts.into_pyarray(py).unbind(),
+        outends.into_pyarray(py).unbind(),
+        outidxs.into_pyarray(py).unbind(),
     ))
 }
 
@@ -595,21 +1439,177 @@ pub fn boundary_numpy(
     Py<PyArray1<i64>>,
     Py<PyArray1<i64>>,
 )> {
-    let chrs_slice = chrs.as_slice()?;
-    let starts_slice = starts.as_slice()?;
-    let ends_slice = ends.as_slice()?;
+    let chrs_slice = &owned(chrs);
+    let starts_slice = &owned(starts);
+    let ends_slice = &owned(ends);
 
     let (outidxs, outstarts, outends, counts) =
-        sweep_line_boundary(chrs_slice, starts_slice, ends_slice);
+        py.allow_threads(|| sweep_line_boundary(chrs_slice, starts_slice, ends_slice));
     Ok((
-        outidxs.into_pyarray(py).to_owned().into(),
-        outstarts.into_pyarray(py).to_owned().into(),
-        outends.into_pyarray(py).to_owned().into(),
-        counts.into_pyarray(py).to_owned().into(),
+        outidxs.into_pyarray(py).unbind(),
+        outstarts.into_pyarray(py).unbind(),
+        outends.into_pyarray(py).unbind(),
+        counts.into_pyarray(py).unbind(),
     ))
 }
 
 
+/// Python handle to an `IntervalIndex`: the collection is sorted once and
+/// every query only sorts its own intervals.
+#[pyclass(name = "IntervalIndex")]
+pub struct PyIntervalIndex {
+    index: IntervalIndex,
+}
+
+#[pymethods]
+impl PyIntervalIndex {
+    #[new]
+    fn new(
+        py: Python,
+        chrs: PyReadonlyArray1<u32>,
+        starts: PyReadonlyArray1<i64>,
+        ends: PyReadonlyArray1<i64>,
+    ) -> PyResult<Self> {
+        let chrs_slice = &owned(chrs);
+        let starts_slice = &owned(starts);
+        let ends_slice = &owned(ends);
+        let index = py
+            .allow_threads(|| IntervalIndex::new(chrs_slice, starts_slice, ends_slice))
+            .map_err(pyo3::exceptions::PyValueError::new_err)?;
+        Ok(PyIntervalIndex { index })
+    }
+
+    fn __len__(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Writes the index to `path` in the versioned ruranges index format.
+    fn save(&self, py: Python, path: std::path::PathBuf) -> PyResult<()> {
+        Ok(py.allow_threads(|| self.index.save(path))?)
+    }
+
+    /// Memory-maps an index previously written with `save`, raising an
+    /// `OSError` if the file is corrupt. The file must not be modified while
+    /// the index is alive; `save` replaces files instead of rewriting them.
+    #[staticmethod]
+    fn load(py: Python, path: std::path::PathBuf) -> PyResult<Self> {
+        Ok(PyIntervalIndex {
+            index: py.allow_threads(|| IntervalIndex::load(path))?,
+        })
+    }
+
+    #[pyo3(signature = (chrs, starts, ends, slack=0))]
+    fn overlaps(
+        &self,
+        py: Python,
+        chrs: PyReadonlyArray1<u32>,
+        starts: PyReadonlyArray1<i64>,
+        ends: PyReadonlyArray1<i64>,
+        slack: i64,
+    ) -> PyResult<(Py<PyArray1<u32>>, Py<PyArray1<u32>>)> {
+        let chrs_slice = &owned(chrs);
+        let starts_slice = &owned(starts);
+        let ends_slice = &owned(ends);
+
+        let (idx, idx2): (Vec<u32>, Vec<u32>) = py.allow_threads(|| {
+            let pairs = self
+                .index
+                .overlaps(chrs_slice, starts_slice, ends_slice, slack);
+            pairs.into_iter().map(|pair| (pair.idx, pair.idx2)).unzip()
+        });
+        Ok((
+            idx.into_pyarray(py).unbind(),
+            idx2.into_pyarray(py).unbind(),
+        ))
+    }
+
+    /// With `out`, the counts are copied into it.
+    #[pyo3(signature = (chrs, starts, ends, slack=0, out=None))]
+    fn count(
+        &self,
+        py: Python,
+        chrs: PyReadonlyArray1<u32>,
+        starts: PyReadonlyArray1<i64>,
+        ends: PyReadonlyArray1<i64>,
+        slack: i64,
+        out: Option<Bound<'_, PyArray1<u32>>>,
+    ) -> PyResult<Py<PyArray1<u32>>> {
+        let chrs_slice = &owned(chrs);
+        let starts_slice = &owned(starts);
+        let ends_slice = &owned(ends);
+
+        check_out(out.as_ref(), chrs_slice.len())?;
+        let counts = py.allow_threads(|| {
+            let mut counts = vec![0; chrs_slice.len()];
+            self.index
+                .count_into(chrs_slice, starts_slice, ends_slice, slack, &mut counts);
+            counts
+        });
+        Ok(into_out(py, out, counts)?.unbind())
+    }
+
+    #[pyo3(signature = (chrs, starts, ends, slack=0, k=1, include_overlaps=true, direction="any", max_distance=None, distance_metric="edge", k_by_intervals=false))]
+    fn nearest(
+        &self,
+        py: Python,
+        chrs: PyReadonlyArray1<u32>,
+        starts: PyReadonlyArray1<i64>,
+        ends: PyReadonlyArray1<i64>,
+        slack: i64,
+        k: usize,
+        include_overlaps: bool,
+        direction: &str,
+        max_distance: Option<i64>,
+        distance_metric: &str,
+        k_by_intervals: bool,
+    ) -> PyResult<(Py<PyArray1<u32>>, Py<PyArray1<u32>>, Py<PyArray1<i64>>)> {
+        let chrs_slice = &owned(chrs);
+        let starts_slice = &owned(starts);
+        let ends_slice = &owned(ends);
+
+        let result = py
+            .allow_threads(|| {
+                let options = NearestOptions {
+                    slack,
+                    k,
+                    include_overlaps,
+                    direction,
+                    max_distance,
+                    distance_metric,
+                    k_by_intervals,
+                    ..NearestOptions::default()
+                };
+                self.index
+                    .nearest(chrs_slice, starts_slice, ends_slice, &options)
+            })
+            .map_err(pyo3::exceptions::PyValueError::new_err)?;
+        Ok((
+            result.0.into_pyarray(py).unbind(),
+            result.1.into_pyarray(py).unbind(),
+            result.2.into_pyarray(py).unbind(),
+        ))
+    }
+
+    fn contains_point(
+        &self,
+        py: Python,
+        chrs: PyReadonlyArray1<u32>,
+        positions: PyReadonlyArray1<i64>,
+    ) -> PyResult<(Py<PyArray1<u32>>, Py<PyArray1<u32>>)> {
+        let chrs_slice = &owned(chrs);
+        let positions_slice = &owned(positions);
+
+        let (idx, idx2): (Vec<u32>, Vec<u32>) = py.allow_threads(|| {
+            let pairs = self.index.contains_point(chrs_slice, positions_slice);
+            pairs.into_iter().map(|pair| (pair.idx, pair.idx2)).unzip()
+        });
+        Ok((
+            idx.into_pyarray(py).unbind(),
+            idx2.into_pyarray(py).unbind(),
+        ))
+    }
+}
+
 #[derive(Debug, PartialEq)]
 enum Direction {
     Forward,
@@ -633,11 +1633,20 @@ impl FromStr for Direction {
 #[pymodule]
 fn ruranges(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(chromsweep_numpy, m)?)?;
+    m.add_function(wrap_pyfunction!(chromsweep_csr_numpy, m)?)?;
+    m.add_function(wrap_pyfunction!(chromsweep_batches_numpy, m)?)?;
+    m.add_class::<PyOverlapBatches>()?;
+    m.add_function(wrap_pyfunction!(count_overlaps_numpy, m)?)?;
     m.add_function(wrap_pyfunction!(complement_overlaps_numpy, m)?)?;
     m.add_function(wrap_pyfunction!(nearest_numpy, m)?)?;
+    m.add_function(wrap_pyfunction!(flanking_neighbours_numpy, m)?)?;
+    m.add_function(wrap_pyfunction!(nearest_points_numpy, m)?)?;
+    m.add_function(wrap_pyfunction!(points_in_intervals_numpy, m)?)?;
+    m.add_class::<PyIntervalIndex>()?;
     m.add_function(wrap_pyfunction!(window_numpy, m)?)?;
     m.add_function(wrap_pyfunction!(tile_numpy, m)?)?;
     m.add_function(wrap_pyfunction!(sort_intervals_numpy, m)?)?;
+    m.add_function(wrap_pyfunction!(coverage_per_bin_numpy, m)?)?;
     // m.add_function(wrap_pyfunction!(nearest_intervals_unique_k_numpy, m)?)?;
     m.add_function(wrap_pyfunction!(cluster_numpy, m)?)?;
     m.add_function(wrap_pyfunction!(complement_numpy, m)?)?;
@@ -651,3 +1660,38 @@ fn ruranges(m: &Bound<'_, PyModule>) -> PyResult<()> {
     // m.add_function(wrap_pyfunction!(nearest_previous_intervals_numpy, m)?)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{random_intervals, Rng};
+
+    #[test]
+    fn test_chromsweep_does_not_depend_on_threads() {
+        let mut rng = Rng::new(41);
+        let (chrs, starts, ends) = random_intervals(&mut rng, 300, 4, 1000, 40);
+        let (chrs2, starts2, ends2) = random_intervals(&mut rng, 300, 4, 1000, 40);
+        for overlap_type in [OverlapType::All, OverlapType::First, OverlapType::Last] {
+            for relation in [OverlapRelation::Any, OverlapRelation::Within] {
+                let sweep = |threads| {
+                    chromsweep(
+                        &chrs,
+                        &starts,
+                        &ends,
+                        &chrs2,
+                        &starts2,
+                        &ends2,
+                        3,
+                        1,
+                        None,
+                        overlap_type,
+                        relation,
+                        threads,
+                        None,
+                    )
+                };
+                assert_eq!(sweep(1), sweep(3), "{:?} {:?}", overlap_type, relation);
+            }
+        }
+    }
+}
@@ -1,13 +1,12 @@
+use std::str::FromStr;
 
-use std::hash::Hash;
-use num_traits::{PrimInt, Signed, Zero}; // You'll need the num-traits crate
-use rustc_hash::{FxHashMap, FxHashSet};
+use rayon::prelude::*;
+use rustc_hash::FxHashMap;
 
-use crate::ruranges_structs::{MaxEvent, MinEvent, OverlapPair};
-use crate::sorts::{
-    self, build_sorted_events_single_collection_separate_outputs,
-    build_sorted_maxevents_with_starts_ends,
-};
+use crate::active_set::ActiveSet;
+use crate::multiprocessing;
+use crate::ruranges_structs::{MinEvent, OverlapPair};
+use crate::sorts::{self, build_sorted_events_single_collection_separate_outputs};
 
 /// Perform a four-way merge sweep to find cross overlaps.
 
@@ -21,148 +20,127 @@ pub enum WhichList {
 
 impl WhichList {
     #[inline]
-    fn is_start(&self) -> bool {
+    pub fn is_start(&self) -> bool {
         match self {
             WhichList::StartSet1 | WhichList::StartSet2 => true,
             WhichList::EndSet1 | WhichList::EndSet2 => false,
         }
     }
-}
-
-/// Returns all overlapping pairs (idx1, idx2) between intervals in set1 and set2.
-/// This uses a line-sweep / active-set approach.
-///
-/// Algorithm steps:
-///   1. Build a list of events (start & end) for each interval in both sets.
-///   2. Sort events by coordinate. Where coordinates tie, put start before end.
-///   3. Maintain active sets (for set1 and set2). For a start event in set1,
-///      record overlap with all active in set2, then insert into active1. Etc.
-///   4. Return the list of all cross-set overlaps.
-pub fn sweep_line_overlaps<T>(
-    chrs: &[u32],
-    starts: &[T],
-    ends: &[T],
-    chrs2: &[u32],
-    starts2: &[T],
-    ends2: &[T],
-    slack: T,
-) -> (Vec<u32>, Vec<u32>)
- where
-    T: PrimInt + Signed + Hash + Copy + radsort::Key + Zero, {
-    // We'll collect all cross overlaps here
-    let mut overlaps = Vec::new();
-    let mut overlaps2 = Vec::new();
-
-    if chrs.is_empty() | chrs2.is_empty() {
-        return (overlaps, overlaps2);
-    };
 
-    let events = sorts::build_sorted_events(chrs, starts, ends, chrs2, starts2, ends2, slack);
-    // Active sets
-    let mut active1 = FxHashSet::default();
-    let mut active2 = FxHashSet::default();
+    #[inline]
+    pub fn is_first_set(&self) -> bool {
+        match self {
+            WhichList::StartSet1 | WhichList::EndSet1 => true,
+            WhichList::StartSet2 | WhichList::EndSet2 => false,
+        }
+    }
+}
 
-    let mut current_chr: u32 = events.first().unwrap().chr;
+/// Merges the sorted start and end events of two sets into one stream in
+/// (chr, pos) order, ends before starts at the same position. This is what
+/// the two-set sweeps iterate over instead of a combined, re-sorted event
+/// vector, so they only ever hold the four sorted lists.
+pub struct FourWayMerge<'a> {
+    sorted_starts: &'a [MinEvent],
+    sorted_ends: &'a [MinEvent],
+    sorted_starts2: &'a [MinEvent],
+    sorted_ends2: &'a [MinEvent],
+    i1: usize, // pointer into sorted_starts  (set 1)
+    i2: usize, // pointer into sorted_starts2 (set 2)
+    i3: usize, // pointer into sorted_ends    (set 1)
+    i4: usize, // pointer into sorted_ends2   (set 2)
+}
 
-    // Process events in ascending order of position
-    for e in events {
-        if e.chr != current_chr {
-            active1.clear();
-            active2.clear();
-            current_chr = e.chr;
+impl<'a> FourWayMerge<'a> {
+    pub fn new(
+        sorted_starts: &'a [MinEvent],
+        sorted_ends: &'a [MinEvent],
+        sorted_starts2: &'a [MinEvent],
+        sorted_ends2: &'a [MinEvent],
+    ) -> Self {
+        FourWayMerge {
+            sorted_starts,
+            sorted_ends,
+            sorted_starts2,
+            sorted_ends2,
+            i1: 0,
+            i2: 0,
+            i3: 0,
+            i4: 0,
         }
+    }
+}
 
-        if e.is_start {
-            // Interval is starting
-            if e.first_set {
-                // Overlaps with all currently active intervals in set2
-                for &idx2 in active2.iter() {
-                    overlaps.push(e.idx);
-                    overlaps2.push(idx2);
-                }
-                // Now add it to active1
-                active1.insert(e.idx);
-            } else {
-                // Overlaps with all currently active intervals in set1
-                for &idx1 in active1.iter() {
-                    overlaps.push(idx1);
-                    overlaps2.push(e.idx);
-                }
-                // Now add it to active2
-                active2.insert(e.idx);
-            }
-        } else {
-            // Interval is ending
-            if e.first_set {
-                active1.remove(&e.idx);
-            } else {
-                active2.remove(&e.idx);
-            }
+impl<'a> Iterator for FourWayMerge<'a> {
+    type Item = (WhichList, &'a MinEvent);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (which_list, event) = pick_winner_of_four(
+            self.sorted_starts
+                .get(self.i1)
+                .map(|e| (WhichList::StartSet1, e)),
+            self.sorted_starts2
+                .get(self.i2)
+                .map(|e| (WhichList::StartSet2, e)),
+            self.sorted_ends
+                .get(self.i3)
+                .map(|e| (WhichList::EndSet1, e)),
+            self.sorted_ends2
+                .get(self.i4)
+                .map(|e| (WhichList::EndSet2, e)),
+        )?;
+        // Advance the pointer for whichever list we took an event from
+        match which_list {
+            WhichList::StartSet1 => self.i1 += 1,
+            WhichList::StartSet2 => self.i2 += 1,
+            WhichList::EndSet1 => self.i3 += 1,
+            WhichList::EndSet2 => self.i4 += 1,
         }
+        Some((which_list, event))
     }
 
-    (overlaps, overlaps2)
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.sorted_starts.len()
+            + self.sorted_starts2.len()
+            + self.sorted_ends.len()
+            + self.sorted_ends2.len()
+            - self.i1
+            - self.i2
+            - self.i3
+            - self.i4;
+        (remaining, Some(remaining))
+    }
 }
 
-pub fn sweep_line_overlaps_set1(
+/// Returns all overlapping pairs (idx1, idx2) between intervals in set1 and set2,
+/// in sweep order. Set 1 intervals are extended by `slack_left`/`slack_right`;
+/// when `strand_flags` is given the two are swapped for reverse-strand intervals.
+///
+/// The sorted starts and `overlap_end`s of both sets are swept with a four-way
+/// merge, so an empty interval overlaps the intervals containing its position.
+pub fn sweep_line_overlaps(
     chrs: &[u32],
     starts: &[i64],
     ends: &[i64],
     chrs2: &[u32],
     starts2: &[i64],
     ends2: &[i64],
-    slack: i64,
-) -> Vec<u32> {
-    // We'll collect all cross overlaps here
-    let mut overlaps = Vec::new();
-
-    if chrs.is_empty() | chrs2.is_empty() {
-        return overlaps;
-    };
-
-    let events = sorts::build_sorted_events(chrs, starts, ends, chrs2, starts2, ends2, slack);
-
-    // Active sets
-    let mut active1 = FxHashSet::default();
-    let mut active2 = FxHashSet::default();
-
-    let mut current_chr: u32 = events.first().unwrap().chr;
-
-    // Process events in ascending order of position
-    for e in events {
-        if e.chr != current_chr {
-            active1.clear();
-            current_chr = e.chr;
-        }
-
-        if e.is_start {
-            // Interval is starting
-            if e.first_set {
-                // Overlaps with all currently active intervals in set2
-                for &_idx2 in active2.iter() {
-                    overlaps.push(e.idx);
-                }
-                // Now add it to active1
-                active1.insert(e.idx);
-            } else {
-                // Overlaps with all currently active intervals in set1
-                for &idx1 in active1.iter() {
-                    overlaps.push(idx1);
-                }
-                // Now add it to active2
-                active2.insert(e.idx);
-            }
-        } else {
-            // Interval is ending
-            if e.first_set {
-                active1.remove(&e.idx);
-            } else {
-                active2.remove(&e.idx);
-            }
-        }
-    }
+    slack_left: i64,
+    slack_right: i64,
+    strand_flags: Option<&[bool]>,
+) -> (Vec<u32>, Vec<u32>) {
+    let (starts, ends) =
+        overlap_coordinates(starts, ends, slack_left, slack_right, strand_flags, false);
+    let (starts2, ends2) = overlap_coordinates(starts2, ends2, 0, 0, None, false);
+    let sorted_starts = build_sorted_events_single_collection_separate_outputs(chrs, &starts, 0);
+    let sorted_ends = build_sorted_events_single_collection_separate_outputs(chrs, &ends, 0);
+    let sorted_starts2 = build_sorted_events_single_collection_separate_outputs(chrs2, &starts2, 0);
+    let sorted_ends2 = build_sorted_events_single_collection_separate_outputs(chrs2, &ends2, 0);
 
-    overlaps
+    sweep_line_overlaps_overlap_pair(&sorted_starts, &sorted_ends, &sorted_starts2, &sorted_ends2)
+        .into_iter()
+        .map(|pair| (pair.idx, pair.idx2))
+        .unzip()
 }
 
 pub fn sweep_line_overlaps_overlap_pair(
@@ -177,137 +155,286 @@ pub fn sweep_line_overlaps_overlap_pair(
         return out_idxs;
     }
     // Active intervals for set1, set2
-    let mut active1 = FxHashSet::default();
-    let mut active2 = FxHashSet::default();
-    // Pointers into each list
-    let mut i1 = 0usize; // pointer into sorted_starts  (set 1)
-    let mut i2 = 0usize; // pointer into sorted_starts2 (set 2)
-    let mut i3 = 0usize; // pointer into sorted_ends    (set 1)
-    let mut i4 = 0usize; // pointer into sorted_ends2   (set 2)
-                         // Figure out the very first chromosome we encounter (if any):
-                         // We'll look at the heads of each list and pick the lexicographically smallest.
-    let first_candidate = pick_winner_of_four(
-        sorted_starts.get(i1).map(|e| (WhichList::StartSet1, e)),
-        sorted_starts2.get(i2).map(|e| (WhichList::StartSet2, e)),
-        sorted_ends.get(i3).map(|e| (WhichList::EndSet1, e)),
-        sorted_ends2.get(i4).map(|e| (WhichList::EndSet2, e)),
-    );
-    // Unwrap the first candidate’s chromosome
-    let mut current_chr = first_candidate.unwrap().1.chr;
+    let mut active1 = ActiveSet::with_capacity(sorted_starts.len());
+    let mut active2 = ActiveSet::with_capacity(sorted_starts2.len());
+    let mut current_chr = sorted_starts[0].chr.min(sorted_starts2[0].chr);
     // Main sweep-line loop
-    while i1 < sorted_starts.len()
-        || i2 < sorted_starts2.len()
-        || i3 < sorted_ends.len()
-        || i4 < sorted_ends2.len()
+    for (which_list, event) in
+        FourWayMerge::new(sorted_starts, sorted_ends, sorted_starts2, sorted_ends2)
     {
-        let (which_list, event) = if let Some((which_list, event)) = pick_winner_of_four(
-            sorted_starts.get(i1).map(|e| (WhichList::StartSet1, e)),
-            sorted_starts2.get(i2).map(|e| (WhichList::StartSet2, e)),
-            sorted_ends.get(i3).map(|e| (WhichList::EndSet1, e)),
-            sorted_ends2.get(i4).map(|e| (WhichList::EndSet2, e)),
-        ) {
-            (which_list, event)
-        } else {
-            break;
-        };
         // If we've moved to a new chromosome, reset active sets
         if event.chr != current_chr {
             active1.clear();
             active2.clear();
             current_chr = event.chr;
         }
-        // Advance the pointer for whichever list we took an event from
         match which_list {
             WhichList::StartSet1 => {
-                for &idx2 in active2.iter() {
+                for idx2 in active2.idxs() {
                     out_idxs.push(OverlapPair {
                         idx: event.idx,
-                        idx2: idx2,
+                        idx2,
                     })
                 }
                 // Now add it to active1
-                active1.insert(event.idx);
-                i1 += 1
+                active1.insert(event.idx, ());
             }
             WhichList::StartSet2 => {
-                for &idx1 in active1.iter() {
+                for idx1 in active1.idxs() {
                     out_idxs.push(OverlapPair {
                         idx: idx1,
                         idx2: event.idx,
                     })
                 }
                 // Now add it to active2
-                active2.insert(event.idx);
-                i2 += 1
+                active2.insert(event.idx, ());
             }
             WhichList::EndSet1 => {
-                active1.remove(&event.idx);
-                i3 += 1
+                active1.remove(event.idx);
             }
             WhichList::EndSet2 => {
-                active2.remove(&event.idx);
-                i4 += 1
+                active2.remove(event.idx);
             }
         }
     }
     out_idxs
 }
 
-pub fn sweep_line_overlaps_containment(events: Vec<MaxEvent>) -> (Vec<OverlapPair>) {
+/// Which relation between a set 1 and a set 2 interval counts as a hit
+/// (Allen's interval relations, with the containment ones non-strict).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OverlapRelation {
+    /// Any overlap.
+    Any,
+    /// The set 1 interval lies within the set 2 interval.
+    Within,
+    /// The set 1 interval contains the set 2 interval.
+    Contains,
+    /// Both intervals have the same start and end.
+    Equal,
+    /// Both intervals start at the same position.
+    Starts,
+    /// Both intervals end at the same position.
+    Finishes,
+    /// The set 1 interval ends where the set 2 interval starts.
+    Meets,
+    /// The set 2 interval ends where the set 1 interval starts.
+    MetBy,
+}
+
+impl OverlapRelation {
+    #[inline]
+    fn holds(&self, start: i64, end: i64, start2: i64, end2: i64) -> bool {
+        match self {
+            OverlapRelation::Any => true,
+            OverlapRelation::Within => start >= start2 && end <= end2,
+            OverlapRelation::Contains => start <= start2 && end >= end2,
+            OverlapRelation::Equal => start == start2 && end == end2,
+            OverlapRelation::Starts => start == start2,
+            OverlapRelation::Finishes => end == end2,
+            OverlapRelation::Meets => end == start2,
+            OverlapRelation::MetBy => end2 == start,
+        }
+    }
+
+    /// Whether the relation holds between intervals that do not overlap.
+    #[inline]
+    fn is_abutting(&self) -> bool {
+        matches!(self, OverlapRelation::Meets | OverlapRelation::MetBy)
+    }
+
+    /// The same relation seen in negated coordinates (the "last" overlap type
+    /// sweeps right to left), where starts and ends trade places.
+    pub fn mirrored(&self) -> Self {
+        match self {
+            OverlapRelation::Starts => OverlapRelation::Finishes,
+            OverlapRelation::Finishes => OverlapRelation::Starts,
+            OverlapRelation::Meets => OverlapRelation::MetBy,
+            OverlapRelation::MetBy => OverlapRelation::Meets,
+            other => *other,
+        }
+    }
+}
+
+impl FromStr for OverlapRelation {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "any" | "overlaps" => Ok(OverlapRelation::Any),
+            "within" | "during" | "contained" => Ok(OverlapRelation::Within),
+            "contains" => Ok(OverlapRelation::Contains),
+            "equal" => Ok(OverlapRelation::Equal),
+            "starts" | "same_start" => Ok(OverlapRelation::Starts),
+            "finishes" | "same_end" => Ok(OverlapRelation::Finishes),
+            "meets" => Ok(OverlapRelation::Meets),
+            "met_by" => Ok(OverlapRelation::MetBy),
+            _ => Err("Invalid overlap relation string"),
+        }
+    }
+}
+
+pub fn sweep_line_overlaps_containment(
+    chrs: &[u32],
+    starts: &[i64],
+    ends: &[i64],
+    chrs2: &[u32],
+    starts2: &[i64],
+    ends2: &[i64],
+) -> Vec<OverlapPair> {
+    sweep_line_overlaps_relation(
+        chrs,
+        starts,
+        ends,
+        chrs2,
+        starts2,
+        ends2,
+        OverlapRelation::Within,
+    )
+}
+
+/// Returns all pairs (idx1, idx2) for which `relation` holds. Overlapping
+/// relations are checked against the active intervals of the other set when an
+/// interval starts; `Meets`/`MetBy` against the intervals of the other set that
+/// ended at exactly that position.
+///
+/// The events only carry the position being swept over, so the coordinates of
+/// the intervals are looked up in `starts`/`ends` by idx.
+pub fn sweep_line_overlaps_relation(
+    chrs: &[u32],
+    starts: &[i64],
+    ends: &[i64],
+    chrs2: &[u32],
+    starts2: &[i64],
+    ends2: &[i64],
+    relation: OverlapRelation,
+) -> Vec<OverlapPair> {
+    let sorted_starts = build_sorted_events_single_collection_separate_outputs(chrs, starts, 0);
+    let sorted_ends = build_sorted_events_single_collection_separate_outputs(chrs, ends, 0);
+    let sorted_starts2 = build_sorted_events_single_collection_separate_outputs(chrs2, starts2, 0);
+    let sorted_ends2 = build_sorted_events_single_collection_separate_outputs(chrs2, ends2, 0);
+
+    sweep_line_overlaps_relation_sorted(
+        &sorted_starts,
+        &sorted_ends,
+        &sorted_starts2,
+        &sorted_ends2,
+        starts,
+        ends,
+        starts2,
+        ends2,
+        relation,
+    )
+}
+
+/// `sweep_line_overlaps_relation` on already sorted events. The events may be
+/// any chromosome-aligned part of the sets; `starts`/`ends` always cover the
+/// whole sets.
+pub fn sweep_line_overlaps_relation_sorted(
+    sorted_starts: &[MinEvent],
+    sorted_ends: &[MinEvent],
+    sorted_starts2: &[MinEvent],
+    sorted_ends2: &[MinEvent],
+    starts: &[i64],
+    ends: &[i64],
+    starts2: &[i64],
+    ends2: &[i64],
+    relation: OverlapRelation,
+) -> Vec<OverlapPair> {
     // We'll collect all cross overlaps here
     let mut overlaps = Vec::new();
 
-    if events.is_empty() {
+    if sorted_starts.is_empty() || sorted_starts2.is_empty() {
         return overlaps;
     };
 
     // Active sets
-    let mut active1 = FxHashMap::default();
-    let mut active2 = FxHashMap::default();
+    let mut active1 = ActiveSet::with_capacity(starts.len());
+    let mut active2 = ActiveSet::with_capacity(starts2.len());
+
+    // Intervals that ended at `ended_pos` (only tracked for abutting relations).
+    // Ends sort before starts at the same position, so they are all known
+    // by the time the first start there is processed.
+    let mut ended1: Vec<u32> = Vec::new();
+    let mut ended2: Vec<u32> = Vec::new();
+    let mut ended_pos = i64::MIN;
 
-    let mut current_chr: u32 = events.first().unwrap().chr;
+    let mut current_chr: u32 = sorted_starts[0].chr.min(sorted_starts2[0].chr);
 
     // Process events in ascending order of position
-    for e in events {
+    for (which_list, e) in
+        FourWayMerge::new(sorted_starts, sorted_ends, sorted_starts2, sorted_ends2)
+    {
         if e.chr != current_chr {
             active1.clear();
             active2.clear();
+            ended1.clear();
+            ended2.clear();
+            ended_pos = i64::MIN;
             current_chr = e.chr;
         }
 
-        if e.is_start {
-            // Interval is starting
-            if e.first_set {
-                // Overlaps with all currently active intervals in set2
-                for (&idx2, &(start2, end2)) in active2.iter() {
-                    if e.start >= start2 && e.end <= end2 {
-                        overlaps.push(OverlapPair {
-                            idx: e.idx,
-                            idx2: idx2,
-                        });
+        let (start, end) = if which_list.is_first_set() {
+            (starts[e.idx as usize], ends[e.idx as usize])
+        } else {
+            (starts2[e.idx as usize], ends2[e.idx as usize])
+        };
+
+        match which_list {
+            WhichList::StartSet1 => {
+                // Check against all currently active intervals in set2
+                for idx2 in active2.idxs() {
+                    if relation.holds(start, end, starts2[idx2 as usize], ends2[idx2 as usize]) {
+                        overlaps.push(OverlapPair { idx: e.idx, idx2 });
                     };
                 }
+                if relation.is_abutting() && ended_pos == e.pos {
+                    for &idx2 in ended2.iter() {
+                        if relation.holds(start, end, starts2[idx2 as usize], ends2[idx2 as usize])
+                        {
+                            overlaps.push(OverlapPair { idx: e.idx, idx2 });
+                        }
+                    }
+                }
                 // Now add it to active1
-                active1.insert(e.idx, (e.start, e.end));
-            } else {
-                // Overlaps with all currently active intervals in set1
-                for (&idx, &(start, end)) in active1.iter() {
-                    if e.start <= start && e.end >= end {
-                        overlaps.push(OverlapPair {
-                            idx: idx,
-                            idx2: e.idx,
-                        });
+                active1.insert(e.idx, ());
+            }
+            WhichList::StartSet2 => {
+                // Check against all currently active intervals in set1
+                for idx in active1.idxs() {
+                    if relation.holds(starts[idx as usize], ends[idx as usize], start, end) {
+                        overlaps.push(OverlapPair { idx, idx2: e.idx });
                     };
                 }
+                if relation.is_abutting() && ended_pos == e.pos {
+                    for &idx in ended1.iter() {
+                        if relation.holds(starts[idx as usize], ends[idx as usize], start, end) {
+                            overlaps.push(OverlapPair { idx, idx2: e.idx });
+                        }
+                    }
+                }
                 // Now add it to active2
-                active2.insert(e.idx, (e.start, e.end));
+                active2.insert(e.idx, ());
             }
-        } else {
-            // Interval is ending
-            if e.first_set {
-                active1.remove(&e.idx);
-            } else {
-                active2.remove(&e.idx);
+            WhichList::EndSet1 | WhichList::EndSet2 => {
+                // Interval is ending
+                if relation.is_abutting() {
+                    if e.pos != ended_pos {
+                        ended1.clear();
+                        ended2.clear();
+                        ended_pos = e.pos;
+                    }
+                    if which_list.is_first_set() {
+                        ended1.push(e.idx);
+                    } else {
+                        ended2.push(e.idx);
+                    }
+                }
+                if which_list.is_first_set() {
+                    active1.remove(e.idx);
+                } else {
+                    active2.remove(e.idx);
+                }
             }
         }
     }
@@ -315,6 +442,321 @@ pub fn sweep_line_overlaps_containment(events: Vec<MaxEvent>) -> (Vec<OverlapPai
     overlaps
 }
 
+/// Groups overlap pairs by query in compressed sparse row form: the targets of
+/// query `i` (out of `n`) are `targets[offsets[i]..offsets[i + 1]]`, in
+/// ascending order. A counting sort, so linear in the number of pairs.
+///
+/// The pairs are grouped after the sweep, so at the peak the flat pairs (8 bytes
+/// per pair) and the CSR arrays (4 bytes per pair and 16 per query, counting the
+/// write cursors) are held together.
+pub fn overlaps_to_csr(n: usize, idx: &[u32], idx2: &[u32]) -> (Vec<usize>, Vec<u32>) {
+    let mut offsets = vec![0usize; n + 1];
+    for &i in idx {
+        offsets[i as usize + 1] += 1;
+    }
+    for i in 0..n {
+        offsets[i + 1] += offsets[i];
+    }
+
+    let mut next = offsets[..n].to_vec();
+    let mut targets = vec![0u32; idx.len()];
+    for (&i, &j) in idx.iter().zip(idx2.iter()) {
+        targets[next[i as usize]] = j;
+        next[i as usize] += 1;
+    }
+    for i in 0..n {
+        targets[offsets[i]..offsets[i + 1]].sort_unstable();
+    }
+
+    (offsets, targets)
+}
+
+/// Returns all pairs (point idx, interval idx) where the interval in set 2
+/// contains the position, i.e. `start2 - slack <= pos < end2 + slack`.
+pub fn points_in_intervals(
+    chrs: &[u32],
+    positions: &[i64],
+    chrs2: &[u32],
+    starts2: &[i64],
+    ends2: &[i64],
+    slack: i64,
+) -> Vec<OverlapPair> {
+    if chrs.is_empty() || chrs2.is_empty() {
+        return Vec::new();
+    };
+
+    let sorted_positions =
+        build_sorted_events_single_collection_separate_outputs(chrs, positions, 0);
+    let sorted_starts2 =
+        build_sorted_events_single_collection_separate_outputs(chrs2, starts2, slack);
+    let sorted_ends2 = build_sorted_events_single_collection_separate_outputs(chrs2, ends2, -slack);
+
+    points_in_sorted_intervals(&sorted_positions, &sorted_starts2, &sorted_ends2)
+}
+
+/// `points_in_intervals` on already sorted positions and interval starts/ends.
+pub fn points_in_sorted_intervals(
+    sorted_positions: &[MinEvent],
+    sorted_starts2: &[MinEvent],
+    sorted_ends2: &[MinEvent],
+) -> Vec<OverlapPair> {
+    let mut overlaps = Vec::new();
+
+    let mut active2 = ActiveSet::with_capacity(sorted_starts2.len());
+    let mut i = 0usize; // pointer into sorted_starts2
+    let mut j = 0usize; // pointer into sorted_ends2
+
+    for p in sorted_positions {
+        // Everything that starts at or before the position becomes active...
+        while i < sorted_starts2.len()
+            && (sorted_starts2[i].chr, sorted_starts2[i].pos) <= (p.chr, p.pos)
+        {
+            active2.insert(sorted_starts2[i].idx, ());
+            i += 1;
+        }
+        // ...unless it has also ended (ends are exclusive)
+        while j < sorted_ends2.len() && (sorted_ends2[j].chr, sorted_ends2[j].pos) <= (p.chr, p.pos)
+        {
+            active2.remove(sorted_ends2[j].idx);
+            j += 1;
+        }
+
+        for idx2 in active2.idxs() {
+            overlaps.push(OverlapPair { idx: p.idx, idx2 });
+        }
+    }
+
+    overlaps
+}
+
+/// For each interval in set 1, the number of intervals in set 2 overlapping it.
+/// With `threads > 1` the queries are counted in that many contiguous chunks
+/// in parallel; the counts stay in query order.
+pub fn count_overlaps(
+    chrs: &[u32],
+    starts: &[i64],
+    ends: &[i64],
+    chrs2: &[u32],
+    starts2: &[i64],
+    ends2: &[i64],
+    slack: i64,
+    threads: usize,
+) -> Vec<u32> {
+    let mut counts = vec![0; chrs.len()];
+    count_overlaps_into(
+        chrs,
+        starts,
+        ends,
+        chrs2,
+        starts2,
+        ends2,
+        slack,
+        threads,
+        &mut counts,
+    );
+    counts
+}
+
+/// `count_overlaps` writing into `counts`, which must have one slot per query.
+pub fn count_overlaps_into(
+    chrs: &[u32],
+    starts: &[i64],
+    ends: &[i64],
+    chrs2: &[u32],
+    starts2: &[i64],
+    ends2: &[i64],
+    slack: i64,
+    threads: usize,
+    counts: &mut [u32],
+) {
+    count_overlaps_with_progress_into(
+        chrs, starts, ends, chrs2, starts2, ends2, slack, threads, counts, None,
+    );
+}
+
+/// Like `count_overlaps_into`, but counts the queries in chunks of about
+/// `PROGRESS_EVENTS / 2` per thread and reports to `progress` after each chunk,
+/// with two events per query counted so far and no finished chromosomes, as the
+/// queries need not be sorted. Returns `false` once `progress` cancels the count;
+/// `counts` is then only partly filled.
+pub fn count_overlaps_with_progress_into(
+    chrs: &[u32],
+    starts: &[i64],
+    ends: &[i64],
+    chrs2: &[u32],
+    starts2: &[i64],
+    ends2: &[i64],
+    slack: i64,
+    threads: usize,
+    counts: &mut [u32],
+    progress: Option<&dyn multiprocessing::Progress>,
+) -> bool {
+    assert_eq!(
+        counts.len(),
+        chrs.len(),
+        "counts must have one slot per query"
+    );
+    let overlap_ends2: Vec<i64> = starts2
+        .iter()
+        .zip(ends2)
+        .map(|(&start, &end)| overlap_end(start, end))
+        .collect();
+    let sorted_starts2 = sorts::build_sorted_min_events(chrs2, starts2, threads);
+    let sorted_overlap_ends2 = sorts::build_sorted_min_events(chrs2, &overlap_ends2, threads);
+
+    let Some(progress) = progress else {
+        count_overlaps_sorted_par_into(
+            chrs,
+            starts,
+            ends,
+            &sorted_starts2,
+            &sorted_overlap_ends2,
+            slack,
+            threads,
+            counts,
+        );
+        return true;
+    };
+    let step = multiprocessing::PROGRESS_EVENTS / 2 * threads.max(1);
+    let mut done = 0;
+    for counts in counts.chunks_mut(step) {
+        let rows = done..done + counts.len();
+        count_overlaps_sorted_par_into(
+            &chrs[rows.clone()],
+            &starts[rows.clone()],
+            &ends[rows],
+            &sorted_starts2,
+            &sorted_overlap_ends2,
+            slack,
+            threads,
+            counts,
+        );
+        done += counts.len();
+        if !progress.report(2 * done, 0) {
+            return false;
+        }
+    }
+    true
+}
+
+/// `count_overlaps_sorted_into` on `threads` contiguous chunks of the queries.
+fn count_overlaps_sorted_par_into(
+    chrs: &[u32],
+    starts: &[i64],
+    ends: &[i64],
+    sorted_starts2: &[MinEvent],
+    sorted_overlap_ends2: &[MinEvent],
+    slack: i64,
+    threads: usize,
+    counts: &mut [u32],
+) {
+    if threads <= 1 {
+        return count_overlaps_sorted_into(
+            chrs,
+            starts,
+            ends,
+            sorted_starts2,
+            sorted_overlap_ends2,
+            slack,
+            counts,
+        );
+    }
+
+    // The queries are not swept, so any split of the rows works
+    let chunk = chrs.len().div_ceil(threads).max(1);
+    multiprocessing::with_threads(threads, || {
+        counts
+            .par_chunks_mut(chunk)
+            .zip(chrs.par_chunks(chunk))
+            .zip(starts.par_chunks(chunk))
+            .zip(ends.par_chunks(chunk))
+            .for_each(|(((counts, chrs), starts), ends)| {
+                count_overlaps_sorted_into(
+                    chrs,
+                    starts,
+                    ends,
+                    sorted_starts2,
+                    sorted_overlap_ends2,
+                    slack,
+                    counts,
+                )
+            })
+    });
+}
+
+/// Counts, for each query `[start - slack, end + slack)`, the set 2 intervals
+/// overlapping it with two binary searches and no sweep:
+/// (starts2 < end) - (ends2 <= start), both taken up to the query's chromosome.
+/// The chromosome blocks have the same sizes in both sorted arrays, so the
+/// intervals on earlier chromosomes cancel out. Queries need not be sorted.
+///
+/// Empty intervals count as the position they sit at (see `overlap_end`), so
+/// `sorted_overlap_ends2` must hold the `overlap_end`s of set 2, not the plain
+/// ends: an empty interval at a query's start is then not taken as ended. An
+/// empty query is searched as `[start, start + 1)`.
+pub fn count_overlaps_sorted(
+    chrs: &[u32],
+    starts: &[i64],
+    ends: &[i64],
+    sorted_starts2: &[MinEvent],
+    sorted_overlap_ends2: &[MinEvent],
+    slack: i64,
+) -> Vec<u32> {
+    let mut counts = vec![0; chrs.len()];
+    count_overlaps_sorted_into(
+        chrs,
+        starts,
+        ends,
+        sorted_starts2,
+        sorted_overlap_ends2,
+        slack,
+        &mut counts,
+    );
+    counts
+}
+
+/// `count_overlaps_sorted` writing into `counts`, which must have one slot
+/// per query.
+pub fn count_overlaps_sorted_into(
+    chrs: &[u32],
+    starts: &[i64],
+    ends: &[i64],
+    sorted_starts2: &[MinEvent],
+    sorted_overlap_ends2: &[MinEvent],
+    slack: i64,
+    counts: &mut [u32],
+) {
+    assert_eq!(
+        counts.len(),
+        chrs.len(),
+        "counts must have one slot per query"
+    );
+    debug_assert!(
+        ends_after_starts(sorted_starts2, sorted_overlap_ends2),
+        "sorted_overlap_ends2 must hold the overlap_ends of the intervals in sorted_starts2"
+    );
+    for (((count, &chr), &start), &end) in counts.iter_mut().zip(chrs).zip(starts).zip(ends) {
+        let (start, end) = sorts::extend_interval(start, end, slack, slack);
+        let end = overlap_end(start, end);
+        let started = sorted_starts2.partition_point(|e| (e.chr, e.pos) < (chr, end));
+        let ended = sorted_overlap_ends2.partition_point(|e| (e.chr, e.pos) <= (chr, start));
+        // Every interval that ended by the start also started before the end
+        *count = started
+            .checked_sub(ended)
+            .expect("sorted_starts2 and sorted_overlap_ends2 do not hold the same intervals")
+            as u32;
+    }
+}
+
+/// Whether every end is after the start of the same interval, as the
+/// `overlap_end`s are and plain ends of empty intervals are not.
+fn ends_after_starts(sorted_starts: &[MinEvent], sorted_ends: &[MinEvent]) -> bool {
+    let start_of: FxHashMap<u32, i64> = sorted_starts.iter().map(|e| (e.idx, e.pos)).collect();
+    sorted_ends
+        .iter()
+        .all(|e| start_of.get(&e.idx).is_some_and(|&start| start < e.pos))
+}
+
 fn pick_winner_of_four<'a>(
     s1: Option<(WhichList, &'a MinEvent)>,
     s2: Option<(WhichList, &'a MinEvent)>,
@@ -361,64 +803,259 @@ fn pick_winner_of_two_choose_first_if_equal<'a>(
     }
 }
 
-pub fn compute_sorted_events(
-    chrs: &[u32],
+/// The end of `[start, end)` when testing it for overlap. An empty interval
+/// counts as the single position it sits at, `[start, start + 1)`, so it
+/// overlaps the intervals containing that position, as it does in the sweeps.
+#[inline]
+pub fn overlap_end(start: i64, end: i64) -> i64 {
+    end.max(start.saturating_add(1))
+}
+
+/// Start and end coordinates for one set as the sweeps see them: extended by
+/// `slack_left`/`slack_right` (swapped on the reverse strand when
+/// `strand_flags` is given) and, when `invert` is set, mirrored so that the
+/// sweep runs from the end of the chromosome towards the start.
+pub fn sweep_coordinates(
     starts: &[i64],
     ends: &[i64],
-    slack: i64,
+    slack_left: i64,
+    slack_right: i64,
+    strand_flags: Option<&[bool]>,
     invert: bool,
-) -> (Vec<MinEvent>, Vec<MinEvent>) {
+) -> (Vec<i64>, Vec<i64>) {
+    let (starts, ends) =
+        sorts::extend_intervals(starts, ends, slack_left, slack_right, strand_flags);
     if !invert {
-        // "Normal" path
-        let sorted_starts =
-            build_sorted_events_single_collection_separate_outputs(chrs, starts, slack);
-        let sorted_ends = build_sorted_events_single_collection_separate_outputs(chrs, ends, 0);
-        (sorted_starts, sorted_ends)
+        (starts, ends)
     } else {
-        // "Inverted" path
-        let new_starts: Vec<_> = starts.iter().map(|&v| -v).collect();
-        let new_ends: Vec<_> = ends.iter().map(|&v| -v).collect();
-
-        let sorted_starts =
-            build_sorted_events_single_collection_separate_outputs(chrs, &new_ends, slack);
-        let sorted_ends =
-            build_sorted_events_single_collection_separate_outputs(chrs, &new_starts, 0);
-        (sorted_starts, sorted_ends)
+        let new_starts = ends.iter().map(|&v| -v).collect();
+        let new_ends = starts.iter().map(|&v| -v).collect();
+        (new_starts, new_ends)
     }
 }
 
-pub fn compute_sorted_maxevents(
-    chrs: &[u32],
+/// Like `sweep_coordinates`, but with each end moved to its `overlap_end` before
+/// inverting. The event sweeps would otherwise keep an empty interval open until
+/// the end of its partition, so that its hits would depend on the partitioning.
+pub fn overlap_coordinates(
     starts: &[i64],
     ends: &[i64],
-    chrs2: &[u32],
-    starts2: &[i64],
-    ends2: &[i64],
-    slack: i64,
+    slack_left: i64,
+    slack_right: i64,
+    strand_flags: Option<&[bool]>,
     invert: bool,
-) -> Vec<MaxEvent> {
+) -> (Vec<i64>, Vec<i64>) {
+    let (starts, mut ends) =
+        sweep_coordinates(starts, ends, slack_left, slack_right, strand_flags, false);
+    for (end, &start) in ends.iter_mut().zip(&starts) {
+        *end = overlap_end(start, *end);
+    }
     if !invert {
-        // "Normal" path
-        build_sorted_maxevents_with_starts_ends(chrs, starts, ends, chrs2, starts2, ends2, slack)
+        (starts, ends)
     } else {
-        // "Inverted" path
-        let new_starts_vec: Vec<i64> = starts.iter().map(|&v| -v).collect();
-        let new_starts: &[i64] = new_starts_vec.as_slice();
-        let new_ends_vec: Vec<i64> = ends.iter().map(|&v| -v).collect();
-        let new_ends: &[i64] = new_ends_vec.as_slice();
-
-        let new_starts_vec2: Vec<i64> = starts2.iter().map(|&v| -v).collect();
-        let new_starts2: &[i64] = new_starts_vec2.as_slice();
-        let new_ends_vec2: Vec<i64> = ends2.iter().map(|&v| -v).collect();
-        let new_ends2: &[i64] = new_ends_vec2.as_slice();
-        build_sorted_maxevents_with_starts_ends(
-            chrs,
-            new_ends,
-            new_starts,
-            chrs2,
-            new_ends2,
-            new_starts2,
-            slack,
-        )
+        let new_starts = ends.iter().map(|&v| -v).collect();
+        let new_ends = starts.iter().map(|&v| -v).collect();
+        (new_starts, new_ends)
+    }
+}
+
+/// Builds the sorted start and end events for one set, with the coordinates
+/// from `sweep_coordinates`.
+pub fn compute_sorted_events(
+    chrs: &[u32],
+    starts: &[i64],
+    ends: &[i64],
+    slack_left: i64,
+    slack_right: i64,
+    strand_flags: Option<&[bool]>,
+    invert: bool,
+) -> (Vec<MinEvent>, Vec<MinEvent>) {
+    let (starts, ends) =
+        sweep_coordinates(starts, ends, slack_left, slack_right, strand_flags, invert);
+    let sorted_starts = build_sorted_events_single_collection_separate_outputs(chrs, &starts, 0);
+    let sorted_ends = build_sorted_events_single_collection_separate_outputs(chrs, &ends, 0);
+    (sorted_starts, sorted_ends)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{random_intervals, Rng};
+
+    #[test]
+    fn test_counts_match_the_sweep() {
+        let mut rng = Rng::new(34);
+        for threads in [1, 3] {
+            for slack in [0, 4] {
+                let (chrs, starts, ends) = random_intervals(&mut rng, 50, 3, 300, 40);
+                let (chrs2, starts2, ends2) = random_intervals(&mut rng, 80, 3, 300, 40);
+                let (idx, _) = sweep_line_overlaps(
+                    &chrs, &starts, &ends, &chrs2, &starts2, &ends2, slack, slack, None,
+                );
+                let mut expected = vec![0; chrs.len()];
+                for i in idx {
+                    expected[i as usize] += 1;
+                }
+                let counts = count_overlaps(
+                    &chrs, &starts, &ends, &chrs2, &starts2, &ends2, slack, threads,
+                );
+                assert_eq!(counts, expected, "threads={} slack={}", threads, slack);
+            }
+        }
+    }
+
+    #[test]
+    fn test_count_progress_does_not_change_the_counts() {
+        let mut rng = Rng::new(40);
+        let (chrs, starts, ends) = random_intervals(&mut rng, 50, 3, 300, 40);
+        let (chrs2, starts2, ends2) = random_intervals(&mut rng, 80, 3, 300, 40);
+        for threads in [1, 3] {
+            let expected =
+                count_overlaps(&chrs, &starts, &ends, &chrs2, &starts2, &ends2, 2, threads);
+            let reported = std::sync::Mutex::new(0);
+            let report = |events, _| {
+                *reported.lock().unwrap() = events;
+                true
+            };
+            let mut counts = vec![0; chrs.len()];
+            let mut count = |progress: &dyn multiprocessing::Progress| {
+                count_overlaps_with_progress_into(
+                    &chrs,
+                    &starts,
+                    &ends,
+                    &chrs2,
+                    &starts2,
+                    &ends2,
+                    2,
+                    threads,
+                    &mut counts,
+                    Some(progress),
+                )
+            };
+            assert!(count(&report));
+            assert!(!count(&|_, _| false));
+            assert_eq!(counts, expected);
+            assert_eq!(*reported.lock().unwrap(), 2 * chrs.len());
+        }
+    }
+
+    /// Random non-empty intervals, for the relations defined on those.
+    fn non_empty_intervals(rng: &mut Rng, n: usize) -> (Vec<u32>, Vec<i64>, Vec<i64>) {
+        let (chrs, starts, ends) = random_intervals(rng, n, 3, 200, 25);
+        let ends = ends.iter().map(|e| e + 1).collect();
+        (chrs, starts, ends)
+    }
+
+    fn sorted_pairs(pairs: &[OverlapPair]) -> Vec<(u32, u32)> {
+        let mut pairs: Vec<(u32, u32)> = pairs.iter().map(|p| (p.idx, p.idx2)).collect();
+        pairs.sort_unstable();
+        pairs
+    }
+
+    #[test]
+    fn test_overlaps_match_brute_force() {
+        let mut rng = Rng::new(36);
+        for slack in [0, 3] {
+            let (chrs, starts, ends) = random_intervals(&mut rng, 60, 3, 200, 25);
+            let (chrs2, starts2, ends2) = random_intervals(&mut rng, 60, 3, 200, 25);
+
+            let (idx, idx2) = sweep_line_overlaps(
+                &chrs, &starts, &ends, &chrs2, &starts2, &ends2, slack, slack, None,
+            );
+            let mut found: Vec<(u32, u32)> = idx.into_iter().zip(idx2).collect();
+            found.sort_unstable();
+
+            let mut expected = Vec::new();
+            for i in 0..chrs.len() {
+                let (start, end) = ((starts[i] - slack).max(0), ends[i] + slack);
+                for j in 0..chrs2.len() {
+                    if chrs[i] == chrs2[j]
+                        && starts2[j] < overlap_end(start, end)
+                        && start < overlap_end(starts2[j], ends2[j])
+                    {
+                        expected.push((i as u32, j as u32));
+                    }
+                }
+            }
+            assert_eq!(found, expected, "slack={}", slack);
+        }
+    }
+
+    #[test]
+    fn test_relations_match_brute_force() {
+        let mut rng = Rng::new(37);
+        for _ in 0..20 {
+            let (chrs, starts, ends) = non_empty_intervals(&mut rng, 60);
+            let (chrs2, starts2, ends2) = non_empty_intervals(&mut rng, 60);
+
+            for relation in [
+                OverlapRelation::Any,
+                OverlapRelation::Within,
+                OverlapRelation::Contains,
+                OverlapRelation::Equal,
+                OverlapRelation::Starts,
+                OverlapRelation::Finishes,
+                OverlapRelation::Meets,
+                OverlapRelation::MetBy,
+            ] {
+                let found = sweep_line_overlaps_relation(
+                    &chrs, &starts, &ends, &chrs2, &starts2, &ends2, relation,
+                );
+                let mut expected = Vec::new();
+                for i in 0..chrs.len() {
+                    for j in 0..chrs2.len() {
+                        let overlap = starts2[j] < ends[i] && starts[i] < ends2[j];
+                        if chrs[i] == chrs2[j]
+                            && (overlap || relation.is_abutting())
+                            && relation.holds(starts[i], ends[i], starts2[j], ends2[j])
+                        {
+                            expected.push((i as u32, j as u32));
+                        }
+                    }
+                }
+                assert_eq!(sorted_pairs(&found), expected, "{:?}", relation);
+            }
+        }
+    }
+
+    #[test]
+    fn test_overlaps_to_csr_groups_the_targets_by_query() {
+        // Query 1 has no hits and query 3 is past the last one
+        let (offsets, targets) = overlaps_to_csr(4, &[2, 0, 2, 0, 2], &[7, 3, 1, 0, 4]);
+        assert_eq!(offsets, vec![0, 2, 2, 5, 5]);
+        assert_eq!(targets, vec![0, 3, 1, 4, 7]);
+
+        let (offsets, targets) = overlaps_to_csr(2, &[], &[]);
+        assert_eq!((offsets, targets), (vec![0, 0, 0], vec![]));
+    }
+
+    #[test]
+    fn test_empty_intervals_are_counted_where_they_sit() {
+        // Query [10, 20) against empty intervals at 5, 10, 15, 20 and 25
+        let starts2 = [5, 10, 15, 20, 25];
+        let counts = count_overlaps(&[1], &[10], &[20], &[1; 5], &starts2, &starts2, 0, 1);
+        assert_eq!(counts, vec![2]);
+
+        // An empty query at 10 against [0, 10), [5, 15), [10, 20) and empty at 10
+        let counts = count_overlaps(
+            &[1],
+            &[10],
+            &[10],
+            &[1; 4],
+            &[0, 5, 10, 10],
+            &[10, 15, 20, 10],
+            0,
+            1,
+        );
+        assert_eq!(counts, vec![3]);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "overlap_ends")]
+    fn test_plain_ends_are_rejected() {
+        let starts2 = build_sorted_events_single_collection_separate_outputs(&[1], &[10], 0);
+        let ends2 = build_sorted_events_single_collection_separate_outputs(&[1], &[10], 0);
+        count_overlaps_sorted(&[1], &[10], &[20], &starts2, &ends2, 0);
     }
 }
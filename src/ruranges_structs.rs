@@ -39,7 +39,9 @@ pub struct MaxEvent {
     pub idx: u32,
 }
 
+// `repr(C)` so that saved interval indexes can be read in place
 #[derive(Debug, Clone, Hash)]
+#[repr(C)]
 pub struct MinEvent {
     pub chr: u32,
     pub pos: i64,
@@ -1,10 +1,14 @@
 use std::hash::Hash;
+use std::str::FromStr;
 use num_traits::{PrimInt, Signed, Zero}; // You'll need the num-traits crate
 
 use std::collections::HashMap;
 
 use radsort::sort_by_key;
+use rayon::prelude::*;
+use rustc_hash::{FxHashMap, FxHashSet};
 
+use crate::multiprocessing;
 use crate::ruranges_structs::Event;
 use crate::ruranges_structs::EventUsize;
 use crate::ruranges_structs::GenericEvent;
@@ -14,6 +18,142 @@ use crate::ruranges_structs::MinEvent;
 use crate::ruranges_structs::SplicedSubsequenceInterval;
 use crate::ruranges_structs::SubsequenceInterval;
 
+/// Sorts `items` by `(chr(item), key(item))`, doing as little work as the
+/// input order allows:
+///
+/// - already sorted (e.g. coordinate-sorted BED input): nothing is moved;
+/// - every chromosome in one contiguous block, but unsorted within or between
+///   blocks: each block is sorted on `key` alone and the blocks are then put in
+///   chromosome order;
+/// - otherwise: a full stable radix sort.
+///
+/// The check is a single linear pass, which is cheap next to the radix passes.
+pub fn sort_by_chr<E, C, K>(
+    items: &mut Vec<E>,
+    chr: impl Fn(&E) -> C + Sync,
+    key: impl Fn(&E) -> K + Sync,
+) where
+    E: Send,
+    C: radsort::Key + Copy + Ord + Hash + Send,
+    K: radsort::Key + PartialOrd,
+{
+    par_sort_by_chr(items, chr, key, 1)
+}
+
+/// `sort_by_chr` on `threads` threads: with more than one, unsorted input goes
+/// through `bucketed_sort_by_chr`, which sorts the chromosomes in parallel.
+pub fn par_sort_by_chr<E, C, K>(
+    items: &mut Vec<E>,
+    chr: impl Fn(&E) -> C + Sync,
+    key: impl Fn(&E) -> K + Sync,
+    threads: usize,
+) where
+    E: Send,
+    C: radsort::Key + Copy + Ord + Hash + Send,
+    K: radsort::Key + PartialOrd,
+{
+    if items.is_empty() {
+        return;
+    }
+
+    // (chr, start, end) of every run of equal chromosomes
+    let mut blocks: Vec<(C, usize, usize)> = Vec::new();
+    let mut sorted = true;
+    let mut block_start = 0;
+    for i in 1..items.len() {
+        let (prev, cur) = (&items[i - 1], &items[i]);
+        if chr(prev) != chr(cur) {
+            sorted &= chr(prev) < chr(cur);
+            blocks.push((chr(prev), block_start, i));
+            block_start = i;
+        } else {
+            sorted &= key(prev) <= key(cur);
+        }
+    }
+    blocks.push((chr(&items[items.len() - 1]), block_start, items.len()));
+
+    if sorted {
+        return;
+    }
+
+    if threads > 1 {
+        bucketed_sort_by_chr(items, chr, key, threads);
+        return;
+    }
+
+    let mut seen = FxHashSet::default();
+    let grouped = blocks.iter().all(|&(c, _, _)| seen.insert(c));
+    if !grouped {
+        sort_by_key(items, key);
+        sort_by_key(items, chr);
+        return;
+    }
+
+    for &(_, start, end) in &blocks {
+        sort_by_key(&mut items[start..end], &key);
+    }
+
+    if blocks.windows(2).any(|w| w[0].0 > w[1].0) {
+        // Detach the blocks from the back so each element is moved once
+        let mut detached = Vec::with_capacity(blocks.len());
+        for &(c, start, _) in blocks.iter().rev() {
+            detached.push((c, items.split_off(start)));
+        }
+        detached.sort_by_key(|&(c, _)| c);
+        for (_, block) in detached {
+            items.extend(block);
+        }
+    }
+}
+
+/// Stable sort of `items` by `(chr(item), key(item))` that first distributes
+/// the items into one bucket per chromosome and then radix-sorts the buckets
+/// in parallel on a pool of `threads` threads. The result is the same as that
+/// of `sort_by_chr`; the buckets take a second copy of the items while sorting.
+pub fn bucketed_sort_by_chr<E, C, K>(
+    items: &mut Vec<E>,
+    chr: impl Fn(&E) -> C + Sync,
+    key: impl Fn(&E) -> K + Sync,
+    threads: usize,
+) where
+    E: Send,
+    C: radsort::Key + Copy + Ord + Hash + Send,
+    K: radsort::Key + PartialOrd,
+{
+    let mut bucket_of: FxHashMap<C, usize> = FxHashMap::default();
+    let mut buckets: Vec<(C, Vec<E>)> = Vec::new();
+    for item in items.drain(..) {
+        let c = chr(&item);
+        let bucket = *bucket_of.entry(c).or_insert_with(|| {
+            buckets.push((c, Vec::new()));
+            buckets.len() - 1
+        });
+        buckets[bucket].1.push(item);
+    }
+    buckets.sort_unstable_by_key(|&(c, _)| c);
+
+    // One task per chromosome, so a large one does not hold up the small ones
+    multiprocessing::with_threads(threads, || {
+        buckets
+            .par_iter_mut()
+            .with_max_len(1)
+            .for_each(|(_, bucket)| sort_by_key(bucket, &key))
+    });
+
+    for (_, bucket) in buckets {
+        items.extend(bucket);
+    }
+}
+
+/// Packs `(chr, pos, is_start)` into one radix key,
+/// `chr << 65 | (pos ^ sign) << 1 | is_start`, so that events sort in one
+/// `sort_by_key` call, ends before starts at the same position. `chr` must be
+/// below 2^63.
+#[inline]
+pub fn packed_event_key(chr: u64, pos: i64, is_start: bool) -> u128 {
+    ((chr as u128) << 65) | ((((pos as u64) ^ (1 << 63)) as u128) << 1) | (is_start as u128)
+}
+
 pub fn build_intervals(chrs: &[i64], starts: &[i64], ends: &[i64], slack: i64) -> Vec<Interval> {
     let mut intervals: Vec<Interval> = Vec::with_capacity(chrs.len());
     for i in 0..chrs.len() {
@@ -94,10 +234,10 @@ pub fn build_sorted_intervals(
     let mut intervals = build_intervals(chrs, starts, ends, slack);
 
     if sort_on_ends_too {
-        sort_by_key(&mut intervals, |i| i.end);
+        sort_by_chr(&mut intervals, |i| i.group, |i| (i.start, i.end));
+    } else {
+        sort_by_chr(&mut intervals, |i| i.group, |i| i.start);
     };
-    sort_by_key(&mut intervals, |i| i.start);
-    sort_by_key(&mut intervals, |i| i.group);
 
     intervals
 }
@@ -110,9 +250,7 @@ pub fn build_sorted_subsequence_intervals(
 ) -> Vec<SplicedSubsequenceInterval> {
     let mut intervals = build_subsequence_intervals(chrs, starts, ends, strand_flags);
 
-    sort_by_key(&mut intervals, |i| i.end);
-    sort_by_key(&mut intervals, |i| i.start);
-    sort_by_key(&mut intervals, |i| i.chr);
+    sort_by_chr(&mut intervals, |i| i.chr, |i| (i.start, i.end));
 
     intervals
 }
@@ -128,9 +266,7 @@ pub fn build_sorted_sequence_intervals(
     let mut intervals =
         build_sequence_intervals(chrs, starts, ends, idxs, strand_flags, force_plus_strand);
 
-    sort_by_key(&mut intervals, |i| i.end);
-    sort_by_key(&mut intervals, |i| i.start);
-    sort_by_key(&mut intervals, |i| i.group_id);
+    sort_by_chr(&mut intervals, |i| i.group_id, |i| (i.start, i.end));
 
     intervals
 }
@@ -142,6 +278,135 @@ pub fn sort_order_idx(chrs: &[i64], starts: &[i64], ends: &[i64]) -> Vec<usize>
         .collect()
 }
 
+/// `sort_order_idx` writing into `order`, which must have one slot per interval.
+pub fn sort_order_idx_into(chrs: &[i64], starts: &[i64], ends: &[i64], order: &mut [usize]) {
+    assert_eq!(
+        order.len(),
+        chrs.len(),
+        "order must have one slot per interval"
+    );
+    let intervals = build_sorted_intervals(chrs, starts, ends, 0, true);
+    for (slot, interval) in order.iter_mut().zip(&intervals) {
+        *slot = interval.idx;
+    }
+}
+
+/// How the rows of a result are ordered. The Python bindings default to
+/// `Input`, so results do not depend on the sweep unless `Unordered` is asked for.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OutputOrder {
+    /// By query row, then by target row.
+    Input,
+    /// By the query's (chr, start, end), then by the target's.
+    Genomic,
+    /// In whatever order the sweep produced them. The cheapest option.
+    Unordered,
+}
+
+impl FromStr for OutputOrder {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "input" => Ok(OutputOrder::Input),
+            "genomic" => Ok(OutputOrder::Genomic),
+            "unordered" => Ok(OutputOrder::Unordered),
+            _ => Err("Invalid output order string"),
+        }
+    }
+}
+
+/// The permutation that puts the result rows (`idx[i]`, `idx2[i]`) in
+/// `order`, or `None` when they stay as they are. Rows that compare equal
+/// keep their relative order.
+pub fn pair_order<C: radsort::Key + Copy>(
+    order: OutputOrder,
+    idx: &[u32],
+    idx2: &[u32],
+    chrs: &[C],
+    starts: &[i64],
+    ends: &[i64],
+    chrs2: &[C],
+    starts2: &[i64],
+    ends2: &[i64],
+) -> Option<Vec<usize>> {
+    if order == OutputOrder::Unordered {
+        return None;
+    }
+    let mut rows: Vec<usize> = (0..idx.len()).collect();
+    if order == OutputOrder::Input {
+        sort_by_key(&mut rows, |&r| (idx[r], idx2[r]));
+    } else {
+        // Both sorts are stable, so sorting on the target first gives it as the tie-break
+        sort_by_key(&mut rows, |&r| {
+            let j = idx2[r] as usize;
+            (chrs2[j], starts2[j], ends2[j], idx2[r])
+        });
+        sort_by_key(&mut rows, |&r| {
+            let i = idx[r] as usize;
+            (chrs[i], starts[i], ends[i], idx[r])
+        });
+    }
+    Some(rows)
+}
+
+/// `pair_order` for results with one row index per output row.
+pub fn row_order<C: radsort::Key + Copy>(
+    order: OutputOrder,
+    idx: &[usize],
+    chrs: &[C],
+    starts: &[i64],
+    ends: &[i64],
+) -> Option<Vec<usize>> {
+    if order == OutputOrder::Unordered {
+        return None;
+    }
+    let mut rows: Vec<usize> = (0..idx.len()).collect();
+    if order == OutputOrder::Input {
+        sort_by_key(&mut rows, |&r| idx[r]);
+    } else {
+        sort_by_key(&mut rows, |&r| {
+            let i = idx[r];
+            (chrs[i], starts[i], ends[i], i)
+        });
+    }
+    Some(rows)
+}
+
+/// `values` reordered so that output row `k` is `values[rows[k]]`.
+pub fn permute<T: Copy>(values: &[T], rows: &[usize]) -> Vec<T> {
+    rows.iter().map(|&r| values[r]).collect()
+}
+
+/// `permute` of `values` and `values2` in place, for output buffers that must
+/// not be copied: each cycle of the permutation is walked once, holding one
+/// value of each slice aside. `rows` is used up to mark the rows done.
+pub fn permute_pair_in_place<T: Copy, U: Copy>(
+    values: &mut [T],
+    values2: &mut [U],
+    mut rows: Vec<usize>,
+) {
+    assert!(values.len() == rows.len() && values2.len() == rows.len());
+    for first in 0..rows.len() {
+        if rows[first] == first {
+            continue;
+        }
+        let (held, held2) = (values[first], values2[first]);
+        let mut row = first;
+        loop {
+            let from = std::mem::replace(&mut rows[row], row);
+            if from == first {
+                values[row] = held;
+                values2[row] = held2;
+                break;
+            }
+            values[row] = values[from];
+            values2[row] = values2[from];
+            row = from;
+        }
+    }
+}
+
 fn split_by_chromosome(mut intervals: Vec<Interval>) -> HashMap<i64, Vec<Interval>> {
     let mut result = HashMap::new();
     if intervals.len() == 0 {
@@ -222,12 +487,12 @@ pub fn build_sorted_events_single_position(
             chr: chrs[i],
             pos: if negative_position { -pos } else { pos },
             is_start: start,
-            first_set: first_set,
+            first_set,
             idx: i,
         });
     }
 
-    sort_by_key(&mut events, |e| (e.chr, e.pos, e.is_start));
+    sort_by_chr(&mut events, |e| e.chr, |e| (e.pos, e.is_start));
 
     events
 }
@@ -263,9 +528,7 @@ pub fn build_sorted_events_single_collection(
     // 2. is_start before is_end (if pos ties)
     // (We don't strictly need to tie-break by set_id or idx, but we can.)
 
-    sort_by_key(&mut events, |e| e.is_start);
-    sort_by_key(&mut events, |e| e.pos);
-    sort_by_key(&mut events, |e| e.chr);
+    sort_by_chr(&mut events, |e| e.chr, |e| (e.pos, e.is_start));
 
     events
 }
@@ -286,8 +549,23 @@ pub fn build_sorted_events_single_collection_separate_outputs(
         });
     }
 
-    sort_by_key(&mut out_pos, |e| e.pos);
-    sort_by_key(&mut out_pos, |e| e.chr);
+    sort_by_chr(&mut out_pos, |e| e.chr, |e| e.pos);
+
+    out_pos
+}
+
+/// `build_sorted_events_single_collection_separate_outputs` without slack,
+/// sorted on `threads` threads.
+pub fn build_sorted_min_events(chrs: &[u32], pos: &[i64], threads: usize) -> Vec<MinEvent> {
+    let mut out_pos: Vec<MinEvent> = (0..chrs.len())
+        .map(|i| MinEvent {
+            chr: chrs[i],
+            pos: pos[i],
+            idx: i as u32,
+        })
+        .collect();
+
+    par_sort_by_chr(&mut out_pos, |e| e.chr, |e| e.pos, threads);
 
     out_pos
 }
@@ -308,12 +586,81 @@ pub fn build_sorted_events_with_starts_ends(
         });
     }
 
-    sort_by_key(&mut out_pos, |e| e.pos);
-    sort_by_key(&mut out_pos, |e| e.chr);
+    sort_by_chr(&mut out_pos, |e| e.chr, |e| e.pos);
 
     out_pos
 }
 
+/// The (left, right) extension of interval `i`. On the reverse strand
+/// (`strand_flags[i] == false`) upstream is to the right, so the two swap.
+#[inline]
+pub fn stranded_slack<T: Copy>(
+    i: usize,
+    slack_left: T,
+    slack_right: T,
+    strand_flags: Option<&[bool]>,
+) -> (T, T) {
+    if strand_flags.is_none_or(|f| f[i]) {
+        (slack_left, slack_right)
+    } else {
+        (slack_right, slack_left)
+    }
+}
+
+/// `[start, end)` extended by `left` and `right`. A start the slack moves below 0
+/// is clamped at 0, one that was already negative stays where it is, and the end
+/// saturates, so every sweep sees the same extended coordinates.
+#[inline]
+pub fn extend_interval<T: PrimInt + Signed>(start: T, end: T, left: T, right: T) -> (T, T) {
+    let extended = start.saturating_sub(left).max(start.min(T::zero()));
+    (extended, end.saturating_add(right))
+}
+
+/// Returns the starts and ends extended by `slack_left`/`slack_right`,
+/// swapped on the reverse strand, as in `extend_interval`.
+pub fn extend_intervals(
+    starts: &[i64],
+    ends: &[i64],
+    slack_left: i64,
+    slack_right: i64,
+    strand_flags: Option<&[bool]>,
+) -> (Vec<i64>, Vec<i64>) {
+    let mut new_starts = Vec::with_capacity(starts.len());
+    let mut new_ends = Vec::with_capacity(ends.len());
+    for i in 0..starts.len() {
+        let (left, right) = stranded_slack(i, slack_left, slack_right, strand_flags);
+        let (start, end) = extend_interval(starts[i], ends[i], left, right);
+        new_starts.push(start);
+        new_ends.push(end);
+    }
+    (new_starts, new_ends)
+}
+
+/// Maps the signed chromosome codes of two sets onto `u32` codes with the
+/// same order, so they can be swept as `MinEvent`s. Codes that already fit
+/// are kept as they are; otherwise they are replaced by their rank.
+pub fn chromosome_codes(chrs: &[i64], chrs2: &[i64]) -> (Vec<u32>, Vec<u32>) {
+    let fits = |c: &i64| u32::try_from(*c).is_ok();
+    if chrs.iter().all(fits) && chrs2.iter().all(fits) {
+        return (
+            chrs.iter().map(|&c| c as u32).collect(),
+            chrs2.iter().map(|&c| c as u32).collect(),
+        );
+    }
+
+    let mut unique: Vec<i64> = chrs
+        .iter()
+        .chain(chrs2.iter())
+        .copied()
+        .collect::<FxHashSet<i64>>()
+        .into_iter()
+        .collect();
+    radsort::sort(&mut unique);
+    let rank = |c: &i64| unique.binary_search(c).unwrap() as u32;
+
+    (chrs.iter().map(rank).collect(), chrs2.iter().map(rank).collect())
+}
+
 pub fn build_sorted_events<T>(
     chrs: &[u32],
     starts: &[T],
@@ -321,7 +668,9 @@ pub fn build_sorted_events<T>(
     chrs2: &[u32],
     starts2: &[T],
     ends2: &[T],
-    slack: T,
+    slack_left: T,
+    slack_right: T,
+    strand_flags: Option<&[bool]>,
 ) -> Vec<GenericEvent<T>>
 where
     T: PrimInt + Signed + Hash + Copy + radsort::Key + Zero, {
@@ -329,20 +678,18 @@ where
 
     // Convert set1 intervals into events
     for i in 0..chrs.len() {
+        let (left, right) = stranded_slack(i, slack_left, slack_right, strand_flags);
+        let (start, end) = extend_interval(starts[i], ends[i], left, right);
         events.push(GenericEvent {
             chr: chrs[i],
-            pos: if slack < starts[i] {
-                starts[i] - slack
-            } else {
-                T::zero()
-            },
+            pos: start,
             is_start: true,
             first_set: true,
             idx: i as u32,
         });
         events.push(GenericEvent {
             chr: chrs[i],
-            pos: ends[i].saturating_add(slack),
+            pos: end,
             is_start: false,
             first_set: true,
             idx: i as u32,
@@ -366,9 +713,9 @@ where
         });
     }
 
-    sort_by_key(&mut events, |e| e.is_start);
-    sort_by_key(&mut events, |e| e.pos);
-    sort_by_key(&mut events, |e| e.chr);
+    sort_by_key(&mut events, |e| {
+        packed_event_key(e.chr as u64, e.pos.to_i64().unwrap(), e.is_start)
+    });
 
     events
 }
@@ -427,9 +774,9 @@ pub fn build_sorted_maxevents_with_starts_ends(
         });
     }
 
-    sort_by_key(&mut events, |e| e.is_start);
-    sort_by_key(&mut events, |e| e.pos);
-    sort_by_key(&mut events, |e| e.chr);
+    sort_by_key(&mut events, |e| {
+        packed_event_key(e.chr as u64, e.pos, e.is_start)
+    });
 
     events
 }
@@ -480,9 +827,15 @@ pub fn build_sorted_events_idxs(
         });
     }
 
-    sort_by_key(&mut events, |e| e.is_start);
-    sort_by_key(&mut events, |e| e.pos);
-    sort_by_key(&mut events, |e| e.chr);
+    // The chromosome codes are signed here, so pack them relative to the smallest one
+    let min_chr = chrs.iter().chain(chrs2.iter()).copied().min().unwrap_or(0);
+    let max_chr = chrs.iter().chain(chrs2.iter()).copied().max().unwrap_or(0);
+    match max_chr.checked_sub(min_chr) {
+        Some(_) => sort_by_key(&mut events, |e| {
+            packed_event_key((e.chr - min_chr) as u64, e.pos, e.is_start)
+        }),
+        None => sort_by_chr(&mut events, |e| e.chr, |e| (e.pos, e.is_start)),
+    }
 
     events
 }
@@ -537,3 +890,96 @@ pub fn build_sorted_events_from_intervals(
 
     events
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extended_starts_clamp_at_zero() {
+        let (starts, ends) = extend_intervals(&[3, 10], &[5, 20], 5, 1, Some(&[true, false]));
+        assert_eq!((starts, ends), (vec![0, 9], vec![6, 25]));
+
+        // The two-set events extend set 1 the same way
+        let events = build_sorted_events(&[1], &[3], &[5], &[], &[], &[], 5, 1, None);
+        let positions: Vec<i64> = events.iter().map(|e| e.pos).collect();
+        assert_eq!(positions, vec![0, 6]);
+    }
+
+    #[test]
+    fn test_negative_starts_only_move_by_the_slack() {
+        assert_eq!(extend_interval(-5, 3, 0, 0), (-5, 3));
+        assert_eq!(extend_interval(-5, 3, 2, 0), (-5, 3));
+        assert_eq!(extend_interval(-5, 3, -2, 0), (-3, 3));
+        assert_eq!(extend_interval(4, 9, -2, -1), (6, 8));
+    }
+
+    #[test]
+    fn test_sort_by_chr_matches_a_stable_sort() {
+        let mut rng = crate::test_util::Rng::new(35);
+        // (chr, pos, tag), where the tag records the input order
+        let random: Vec<(u32, i64, usize)> = (0..300)
+            .map(|i| (rng.below(5) as u32, rng.below(50) as i64, i))
+            .collect();
+        let mut sorted = random.clone();
+        sorted.sort_by_key(|&(chr, pos, _)| (chr, pos));
+        // Each chromosome in one block, with the blocks and positions unordered
+        let mut grouped = random.clone();
+        grouped.sort_by_key(|&(chr, _, _)| (chr * 3) % 5);
+
+        for items in [random, sorted, grouped] {
+            let mut expected = items.clone();
+            expected.sort_by_key(|&(chr, pos, _)| (chr, pos));
+            let mut items = items;
+            sort_by_chr(&mut items, |it| it.0, |it| it.1);
+            assert_eq!(items, expected);
+        }
+    }
+
+    #[test]
+    fn test_permute_in_place_matches_permute() {
+        let mut rng = crate::test_util::Rng::new(49);
+        for n in [0, 1, 2, 10, 100] {
+            let values: Vec<i64> = (0..n).map(|_| rng.below(1000) as i64).collect();
+            let values2: Vec<usize> = (0..n).collect();
+            // A random permutation, and the identity
+            let mut rows: Vec<usize> = (0..n).collect();
+            for i in (1..n).rev() {
+                rows.swap(i, rng.below(i as u64 + 1) as usize);
+            }
+            for rows in [rows, (0..n).collect()] {
+                let (mut permuted, mut permuted2) = (values.clone(), values2.clone());
+                permute_pair_in_place(&mut permuted, &mut permuted2, rows.clone());
+                assert_eq!(permuted, permute(&values, &rows));
+                assert_eq!(permuted2, permute(&values2, &rows));
+            }
+        }
+    }
+
+    #[test]
+    fn test_bucketed_sort_matches_sort_by_chr() {
+        let mut rng = crate::test_util::Rng::new(44);
+        // (chr, pos, tag), with few positions so that many keys tie
+        let random: Vec<(u32, i64, usize)> = (0..500)
+            .map(|i| (rng.below(6) as u32, rng.below(20) as i64, i))
+            .collect();
+        let one_chromosome: Vec<(u32, i64, usize)> =
+            random.iter().map(|&(_, pos, tag)| (3, pos, tag)).collect();
+        let mut sorted = random.clone();
+        sort_by_chr(&mut sorted, |it| it.0, |it| it.1);
+
+        for items in [random, one_chromosome, sorted, vec![]] {
+            let mut expected = items.clone();
+            sort_by_chr(&mut expected, |it| it.0, |it| it.1);
+            for threads in [1, 2, 4] {
+                let mut bucketed = items.clone();
+                bucketed_sort_by_chr(&mut bucketed, |it| it.0, |it| it.1, threads);
+                assert_eq!(bucketed, expected);
+
+                let mut parallel = items.clone();
+                par_sort_by_chr(&mut parallel, |it| it.0, |it| it.1, threads);
+                assert_eq!(parallel, expected);
+            }
+        }
+    }
+}
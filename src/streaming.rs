@@ -0,0 +1,431 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+use std::io;
+use std::iter::Peekable;
+
+use crate::bed::invalid_data;
+use crate::overlaps::overlap_end;
+
+/// All (id, id2) pairs of overlapping intervals of two `(chr, start, end, id)`
+/// streams, computed lazily. Empty intervals count as the position they sit
+/// at, as in `overlaps::overlap_end`.
+///
+/// Both streams must be sorted by chromosome and then start (any `Ord` on the
+/// chromosomes works, as long as both streams use the same one). An out of
+/// order item is reported as an `InvalidData` error, after which the iterator
+/// ends. A pair is yielded once the later of its two intervals has been read,
+/// so only the intervals still open at the current position are kept in
+/// memory, and none once the other stream has no more intervals on their
+/// chromosome.
+pub fn overlaps<C, I, J, A, B>(input: A, input2: B) -> Overlaps<C, I, J, A::IntoIter, B::IntoIter>
+where
+    C: Ord + Clone,
+    I: Clone,
+    J: Clone,
+    A: IntoIterator<Item = (C, i64, i64, I)>,
+    B: IntoIterator<Item = (C, i64, i64, J)>,
+{
+    Overlaps {
+        input: input.into_iter().peekable(),
+        input2: input2.into_iter().peekable(),
+        active: Vec::new(),
+        active2: Vec::new(),
+        pending: VecDeque::new(),
+        last: None,
+        last2: None,
+        failed: false,
+    }
+}
+
+/// `(chr, start, end, count)` for every run of intervals of a sorted
+/// `(chr, start, end, id)` stream that overlap or lie within `slack` of each
+/// other, computed lazily. Intervals that only touch are merged only when
+/// `slack > 0`, as in `merge::sweep_line_merge`. Out of order items are
+/// reported as in `overlaps`.
+pub fn merge<C, I, A>(input: A, slack: i64) -> Merge<C, A::IntoIter>
+where
+    C: Ord + Clone,
+    A: IntoIterator<Item = (C, i64, i64, I)>,
+{
+    Merge {
+        input: input.into_iter(),
+        slack,
+        run: None,
+        last: None,
+        failed: false,
+    }
+}
+
+/// `(chr, start, end, depth)` for every maximal run of positions covered by
+/// the same, non-zero number of intervals of a sorted `(chr, start, end, id)`
+/// stream, computed lazily. Only the ends of the open intervals are kept.
+/// Out of order items are reported as in `overlaps`.
+pub fn coverage<C, I, A>(input: A) -> Coverage<C, A::IntoIter>
+where
+    C: Ord + Clone,
+    A: IntoIterator<Item = (C, i64, i64, I)>,
+{
+    Coverage {
+        input: input.into_iter().peekable(),
+        ends: BinaryHeap::new(),
+        chr: None,
+        position: i64::MIN,
+        run: None,
+        last: None,
+        failed: false,
+    }
+}
+
+pub struct Overlaps<C, I, J, A: Iterator, B: Iterator> {
+    input: Peekable<A>,
+    input2: Peekable<B>,
+    // (chr, start, overlap end, id) of the intervals that may still overlap
+    // later ones of the other stream
+    active: Vec<(C, i64, i64, I)>,
+    active2: Vec<(C, i64, i64, J)>,
+    pending: VecDeque<(I, J)>,
+    last: Option<(C, i64)>,
+    last2: Option<(C, i64)>,
+    // Set once an error has been yielded
+    failed: bool,
+}
+
+impl<C, I, J, A, B> Iterator for Overlaps<C, I, J, A, B>
+where
+    C: Ord + Clone,
+    I: Clone,
+    J: Clone,
+    A: Iterator<Item = (C, i64, i64, I)>,
+    B: Iterator<Item = (C, i64, i64, J)>,
+{
+    type Item = io::Result<(I, J)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(pair) = self.pending.pop_front() {
+                return Some(Ok(pair));
+            }
+            if self.failed {
+                return None;
+            }
+
+            let from_first = match (self.input.peek(), self.input2.peek()) {
+                (None, None) => return None,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (Some(a), Some(b)) => (&a.0, a.1) <= (&b.0, b.1),
+            };
+
+            // Every later interval of either stream starts at or after this
+            // one, so both lists drop what ends before it. The new interval
+            // overlaps every interval of the other stream still open at its
+            // start. Each list is only kept while the stream it is matched
+            // against has intervals left on its chromosome.
+            if from_first {
+                let (chr, start, end, id) = self.input.next().unwrap();
+                if let Err(e) = check_sorted(&mut self.last, &chr, start) {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+                let end = overlap_end(start, end);
+                prune(&mut self.active, &chr, start);
+                prune(&mut self.active2, &chr, start);
+                for (_, s, _, id2) in &self.active2 {
+                    if *s < end {
+                        self.pending.push_back((id.clone(), id2.clone()));
+                    }
+                }
+                if !on_chr(self.input.peek(), &chr) {
+                    self.active2.clear();
+                }
+                if on_chr(self.input2.peek(), &chr) {
+                    self.active.push((chr, start, end, id));
+                }
+            } else {
+                let (chr, start, end, id2) = self.input2.next().unwrap();
+                if let Err(e) = check_sorted(&mut self.last2, &chr, start) {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+                let end = overlap_end(start, end);
+                prune(&mut self.active, &chr, start);
+                prune(&mut self.active2, &chr, start);
+                for (_, s, _, id) in &self.active {
+                    if *s < end {
+                        self.pending.push_back((id.clone(), id2.clone()));
+                    }
+                }
+                if !on_chr(self.input2.peek(), &chr) {
+                    self.active.clear();
+                }
+                if on_chr(self.input.peek(), &chr) {
+                    self.active2.push((chr, start, end, id2));
+                }
+            }
+        }
+    }
+}
+
+/// Drops the intervals that can not overlap any interval starting at `start`
+/// on `chr` or later.
+fn prune<C: PartialEq, I>(active: &mut Vec<(C, i64, i64, I)>, chr: &C, start: i64) {
+    active.retain(|(c, _, e, _)| c == chr && *e > start);
+}
+
+/// Whether the next item of a stream is on `chr`.
+fn on_chr<C: PartialEq, I>(next: Option<&(C, i64, i64, I)>, chr: &C) -> bool {
+    next.is_some_and(|(c, _, _, _)| c == chr)
+}
+
+pub struct Merge<C, A> {
+    input: A,
+    slack: i64,
+    // (chr, start, end, count) of the run being extended
+    run: Option<(C, i64, i64, i64)>,
+    last: Option<(C, i64)>,
+    failed: bool,
+}
+
+impl<C, I, A> Iterator for Merge<C, A>
+where
+    C: Ord + Clone,
+    A: Iterator<Item = (C, i64, i64, I)>,
+{
+    type Item = io::Result<(C, i64, i64, i64)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        for (chr, start, end, _) in self.input.by_ref() {
+            if let Err(e) = check_sorted(&mut self.last, &chr, start) {
+                self.failed = true;
+                return Some(Err(e));
+            }
+            match &mut self.run {
+                Some((c, _, run_end, count)) if *c == chr && start < *run_end + self.slack => {
+                    *run_end = (*run_end).max(end);
+                    *count += 1;
+                }
+                _ => {
+                    if let Some(done) = self.run.replace((chr, start, end, 1)) {
+                        return Some(Ok(done));
+                    }
+                }
+            }
+        }
+        self.run.take().map(Ok)
+    }
+}
+
+pub struct Coverage<C, A: Iterator> {
+    input: Peekable<A>,
+    // Ends of the intervals open at `position`
+    ends: BinaryHeap<Reverse<i64>>,
+    chr: Option<C>,
+    position: i64,
+    // (chr, start, end, depth) of the run being extended
+    run: Option<(C, i64, i64, i64)>,
+    last: Option<(C, i64)>,
+    failed: bool,
+}
+
+impl<C, I, A> Iterator for Coverage<C, A>
+where
+    C: Ord + Clone,
+    A: Iterator<Item = (C, i64, i64, I)>,
+{
+    type Item = io::Result<(C, i64, i64, i64)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        loop {
+            // The next position where the depth can change on this chromosome
+            let next_start = match self.input.peek() {
+                Some((chr, start, _, _)) if Some(chr) == self.chr.as_ref() => Some(*start),
+                _ => None,
+            };
+            let next_end = self.ends.peek().map(|&Reverse(end)| end);
+            let pos = match (next_start, next_end) {
+                (Some(start), Some(end)) => start.min(end),
+                (Some(start), None) => start,
+                (None, Some(end)) => end,
+                (None, None) => match self.input.peek() {
+                    None => return self.run.take().map(Ok),
+                    Some((chr, _, _, _)) => {
+                        self.chr = Some(chr.clone());
+                        continue;
+                    }
+                },
+            };
+
+            let depth = self.ends.len() as i64;
+            let from = self.position;
+            while self.ends.peek() == Some(&Reverse(pos)) {
+                self.ends.pop();
+            }
+            while let Some((chr, start, _, _)) = self.input.peek() {
+                if Some(chr) != self.chr.as_ref() || *start != pos {
+                    break;
+                }
+                let (chr, start, end, _) = self.input.next().unwrap();
+                if let Err(e) = check_sorted(&mut self.last, &chr, start) {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+                self.ends.push(Reverse(end));
+            }
+            self.position = pos;
+
+            if depth > 0 && from < pos {
+                match &mut self.run {
+                    Some((chr, _, end, run_depth))
+                        if Some(&*chr) == self.chr.as_ref()
+                            && *end == from
+                            && *run_depth == depth =>
+                    {
+                        *end = pos;
+                    }
+                    _ => {
+                        let chr = self.chr.clone().unwrap();
+                        if let Some(done) = self.run.replace((chr, from, pos, depth)) {
+                            return Some(Ok(done));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// An `InvalidData` error unless `(chr, start)` sorts at or after the last
+/// item of the stream.
+fn check_sorted<C: Ord + Clone>(
+    last: &mut Option<(C, i64)>,
+    chr: &C,
+    start: i64,
+) -> io::Result<()> {
+    let in_order = match last {
+        Some((last_chr, last_start)) if last_chr == chr => start >= *last_start,
+        Some((last_chr, _)) => *last_chr < *chr,
+        None => true,
+    };
+    if !in_order {
+        return Err(invalid_data(
+            "Streams must be sorted by chromosome and start",
+        ));
+    }
+    match last {
+        Some((last_chr, last_start)) if last_chr == chr => *last_start = start,
+        _ => *last = Some((chr.clone(), start)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{random_intervals, Rng};
+
+    #[test]
+    fn test_streams() {
+        let intervals = vec![
+            (1, 0, 10, 'a'),
+            (1, 5, 15, 'b'),
+            (1, 20, 30, 'c'),
+            (2, 0, 5, 'd'),
+        ];
+        let intervals2 = vec![(1, 8, 22, 0), (1, 25, 26, 1), (2, 1, 2, 2)];
+
+        let pairs: Vec<(char, u32)> = overlaps(intervals.clone(), intervals2)
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            pairs,
+            vec![('a', 0), ('b', 0), ('c', 0), ('c', 1), ('d', 2)]
+        );
+
+        let merged: Vec<_> = merge(intervals.clone(), 0)
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(merged, vec![(1, 0, 15, 2), (1, 20, 30, 1), (2, 0, 5, 1)]);
+
+        let depths: Vec<_> = coverage(intervals).collect::<io::Result<_>>().unwrap();
+        assert_eq!(
+            depths,
+            vec![
+                (1, 0, 5, 1),
+                (1, 5, 10, 2),
+                (1, 10, 15, 1),
+                (1, 20, 30, 1),
+                (2, 0, 5, 1)
+            ]
+        );
+    }
+    #[test]
+    fn test_overlaps_match_brute_force() {
+        let mut rng = Rng::new(47);
+        let stream = |(chrs, starts, ends): (Vec<u32>, Vec<i64>, Vec<i64>)| {
+            let mut intervals: Vec<(u32, i64, i64, usize)> = (0..chrs.len())
+                .map(|i| (chrs[i], starts[i], ends[i], i))
+                .collect();
+            intervals.sort_by_key(|&(chr, start, _, _)| (chr, start));
+            intervals
+        };
+        let intervals = stream(random_intervals(&mut rng, 200, 3, 300, 20));
+        // Ends on the first two chromosomes, long before the other stream
+        let early = stream(random_intervals(&mut rng, 50, 2, 30, 20));
+
+        for intervals2 in [intervals.clone(), early] {
+            let mut expected = Vec::new();
+            for &(chr, start, end, i) in &intervals {
+                for &(chr2, start2, end2, j) in &intervals2 {
+                    if chr == chr2
+                        && start < overlap_end(start2, end2)
+                        && start2 < overlap_end(start, end)
+                    {
+                        expected.push((i, j));
+                    }
+                }
+            }
+            expected.sort();
+
+            let mut pairs = overlaps(intervals.clone(), intervals2.clone());
+            let mut found: Vec<(usize, usize)> = pairs.by_ref().map(Result::unwrap).collect();
+            found.sort();
+            assert_eq!(found, expected);
+            assert!(pairs.active.is_empty() && pairs.active2.is_empty());
+        }
+
+        // Nothing is kept once the other stream has ended
+        let mut pairs = overlaps(intervals.clone(), vec![(0, 0, 1, 0)]);
+        while pairs.next().is_some() {}
+        assert!(pairs.active.is_empty());
+    }
+
+    #[test]
+    fn test_unsorted_streams_are_an_error() {
+        let unsorted = vec![(1, 5, 10, 'a'), (1, 0, 10, 'b'), (1, 20, 30, 'c')];
+        let sorted = vec![(1, 0, 100, 0)];
+
+        let mut pairs = overlaps(unsorted.clone(), sorted.clone());
+        assert_eq!(pairs.next().unwrap().unwrap(), ('a', 0));
+        let err = pairs.next().unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(pairs.next().is_none());
+
+        let mut pairs = overlaps(sorted, unsorted.clone());
+        assert!(pairs.find(Result::is_err).is_some());
+        assert!(pairs.next().is_none());
+
+        let mut merged = merge(unsorted.clone(), 0);
+        assert!(merged.next().unwrap().is_err());
+        assert!(merged.next().is_none());
+
+        let mut depths = coverage(unsorted);
+        assert!(depths.find(Result::is_err).is_some());
+        assert!(depths.next().is_none());
+    }
+}
@@ -1,7 +1,10 @@
-use rustc_hash::FxHashMap;
-
-use crate::sorts;
+use crate::active_set::ActiveSet;
+use crate::overlaps::{overlap_end, FourWayMerge};
+use crate::sorts::{self, build_sorted_events_single_collection_separate_outputs};
 
+/// The parts of the set 1 intervals that no set 2 interval covers, as
+/// (index in set 1, start, end). Empty intervals cover nothing, so an empty
+/// set 1 interval has no parts and an empty set 2 interval removes nothing.
 pub fn sweep_line_subtract(
     chrs1: &[i64],
     starts1: &[i64],
@@ -10,13 +13,29 @@ pub fn sweep_line_subtract(
     starts2: &[i64],
     ends2: &[i64],
 ) -> (Vec<usize>, Vec<i64>, Vec<i64>) {
-    // If either set is empty, set1 is unchanged (or trivially subtracted).
+    // If either set is empty, set1 is unchanged but for its empty intervals
     if chrs1.is_empty() || chrs2.is_empty() {
-        return ((0..chrs1.len()).collect(), starts1.to_vec(), ends1.to_vec());
+        let idxs: Vec<usize> = (0..chrs1.len())
+            .filter(|&i| starts1[i] < ends1[i])
+            .collect();
+        let starts = idxs.iter().map(|&i| starts1[i]).collect();
+        let ends = idxs.iter().map(|&i| ends1[i]).collect();
+        return (idxs, starts, ends);
     }
 
-    // Build sorted events
-    let events = sorts::build_sorted_events_idxs(chrs1, starts1, ends1, chrs2, starts2, ends2, 0);
+    // Build the four sorted event lists
+    let (chrs1, chrs2) = sorts::chromosome_codes(chrs1, chrs2);
+    let sorted_starts = build_sorted_events_single_collection_separate_outputs(&chrs1, starts1, 0);
+    // Set 1 ends at its overlap ends, so empty intervals close after opening
+    let overlap_ends1: Vec<i64> = starts1
+        .iter()
+        .zip(ends1)
+        .map(|(&start, &end)| overlap_end(start, end))
+        .collect();
+    let sorted_ends =
+        build_sorted_events_single_collection_separate_outputs(&chrs1, &overlap_ends1, 0);
+    let sorted_starts2 = build_sorted_events_single_collection_separate_outputs(&chrs2, starts2, 0);
+    let sorted_ends2 = build_sorted_events_single_collection_separate_outputs(&chrs2, ends2, 0);
 
     // Output buffers
     let mut result_idxs = Vec::new();
@@ -30,12 +49,16 @@ pub fn sweep_line_subtract(
     // we last started a "valid" sub-interval (when active2_count == 0).
     // i.e. active1[idx] = Some(position) means we are currently capturing
     // a sub-interval for that idx that started at `position`.
-    let mut active1: FxHashMap<usize, Option<i64>> = FxHashMap::default();
+    let mut active1: ActiveSet<Option<i64>> = ActiveSet::with_capacity(chrs1.len());
 
-    let mut current_chr = events.first().unwrap().chr;
+    let mut current_chr = sorted_starts[0].chr.min(sorted_starts2[0].chr);
 
     // We'll sweep in ascending order
-    for e in events.iter() {
+    for (which_list, e) in
+        FourWayMerge::new(&sorted_starts, &sorted_ends, &sorted_starts2, &sorted_ends2)
+    {
+        let idx = e.idx as usize;
+
         // If we jumped to a new chromosome, close out everything
         // because intervals do not cross chromosome boundaries.
         if e.chr != current_chr {
@@ -61,9 +84,9 @@ pub fn sweep_line_subtract(
 
         // --- 2. Now handle the event itself:
 
-        if e.first_set {
+        if which_list.is_first_set() {
             // This event is from set1
-            if e.is_start {
+            if which_list.is_start() {
                 // A set1 interval starts
                 // If we are outside set2 (active2_count==0),
                 // that means we can immediately start capturing a sub-interval.
@@ -76,20 +99,21 @@ pub fn sweep_line_subtract(
             } else {
                 // A set1 interval ends
                 // If we have been capturing a sub-interval for this idx, close it
-                if let Some(start_pos) = active1.get(&e.idx).cloned().unwrap_or(None) {
-                    // We are capturing. End the sub-interval at e.pos
-                    if start_pos < pos {
-                        result_idxs.push(e.idx);
+                if let Some(start_pos) = active1.get(e.idx).cloned().unwrap_or(None) {
+                    // We are capturing. End the sub-interval at its real end
+                    let end = pos.min(ends1[idx]);
+                    if start_pos < end {
+                        result_idxs.push(idx);
                         result_starts.push(start_pos);
-                        result_ends.push(pos);
+                        result_ends.push(end);
                     }
                 }
                 // Remove it from active1
-                active1.remove(&e.idx);
+                active1.remove(e.idx);
             }
         } else {
             // This event is from set2
-            if e.is_start {
+            if which_list.is_start() {
                 // set2 interval starts
                 active2_count += 1;
 
@@ -97,18 +121,18 @@ pub fn sweep_line_subtract(
                 // *all currently capturing intervals in set1* right at this boundary.
                 if active2_count == 1 {
                     // close everyone
-                    for (&idx1, &maybe_start) in active1.iter() {
+                    for (idx1, &maybe_start) in active1.iter() {
                         if let Some(start_pos) = maybe_start {
                             // Close at current event pos (exclusive or inclusive depends on your semantics)
                             if start_pos < pos {
-                                result_idxs.push(idx1);
+                                result_idxs.push(idx1 as usize);
                                 result_starts.push(start_pos);
                                 result_ends.push(pos);
                             }
                         }
                     }
                     // Now, set them all to None, since we cannot capture while set2 is active
-                    for v in active1.values_mut() {
+                    for (_idx1, v) in active1.iter_mut() {
                         *v = None;
                     }
                 }
@@ -141,3 +165,48 @@ pub fn sweep_line_subtract(
 
     (result_idxs, result_starts, result_ends)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{random_intervals, Rng};
+
+    #[test]
+    fn test_subtract_matches_brute_force() {
+        let mut rng = Rng::new(37);
+        for _ in 0..20 {
+            let (chrs, starts, ends) = random_intervals(&mut rng, 40, 3, 200, 25);
+            let (chrs2, starts2, ends2) = random_intervals(&mut rng, 40, 3, 200, 25);
+            let chrs: Vec<i64> = chrs.iter().map(|&c| c as i64).collect();
+            let chrs2: Vec<i64> = chrs2.iter().map(|&c| c as i64).collect();
+
+            // The runs of positions of each interval that no set 2 interval covers
+            let mut expected = Vec::new();
+            for i in 0..chrs.len() {
+                let covered = |p: i64| {
+                    (0..chrs2.len()).any(|j| chrs2[j] == chrs[i] && starts2[j] <= p && p < ends2[j])
+                };
+                let mut run_start = None;
+                for p in starts[i]..=ends[i] {
+                    match (run_start, p < ends[i] && !covered(p)) {
+                        (None, true) => run_start = Some(p),
+                        (Some(start), false) => {
+                            expected.push((i, start, p));
+                            run_start = None;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            expected.sort_unstable();
+
+            let (idxs, sub_starts, sub_ends) =
+                sweep_line_subtract(&chrs, &starts, &ends, &chrs2, &starts2, &ends2);
+            let mut found: Vec<(usize, i64, i64)> = (0..idxs.len())
+                .map(|r| (idxs[r], sub_starts[r], sub_ends[r]))
+                .collect();
+            found.sort_unstable();
+            assert_eq!(found, expected);
+        }
+    }
+}
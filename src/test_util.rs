@@ -0,0 +1,39 @@
+//! Deterministic random inputs for the tests.
+
+/// A xorshift generator, so that failing cases can be replayed from the seed.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed.max(1))
+    }
+
+    /// A value in `0..bound`.
+    pub fn below(&mut self, bound: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % bound
+    }
+}
+
+/// `n` random `(chrs, starts, ends)` on `n_chroms` chromosomes, with starts
+/// below `span` and lengths below `max_len`, so some intervals are empty.
+pub fn random_intervals(
+    rng: &mut Rng,
+    n: usize,
+    n_chroms: u32,
+    span: i64,
+    max_len: i64,
+) -> (Vec<u32>, Vec<i64>, Vec<i64>) {
+    let mut chrs = Vec::with_capacity(n);
+    let mut starts = Vec::with_capacity(n);
+    let mut ends = Vec::with_capacity(n);
+    for _ in 0..n {
+        let start = rng.below(span as u64) as i64;
+        chrs.push(rng.below(n_chroms as u64) as u32);
+        starts.push(start);
+        ends.push(start + rng.below(max_len as u64) as i64);
+    }
+    (chrs, starts, ends)
+}
@@ -0,0 +1,320 @@
+import numpy as np
+import pytest
+
+import ruranges
+
+
+def intervals(rows):
+    chrs = np.array([r[0] for r in rows], dtype=np.uint32)
+    starts = np.array([r[1] for r in rows], dtype=np.int64)
+    ends = np.array([r[2] for r in rows], dtype=np.int64)
+    return chrs, starts, ends
+
+
+QUERIES = intervals([(2, 0, 10), (1, 30, 40), (1, 0, 20), (2, 5, 6)])
+TARGETS = intervals([(1, 35, 36), (2, 0, 3), (1, 10, 32), (2, 4, 8), (1, 5, 6)])
+
+
+def chromsweep(**kwargs):
+    chrs, starts, ends = QUERIES
+    chrs2, starts2, ends2 = TARGETS
+    return ruranges.chromsweep_numpy(chrs, starts, ends, chrs2, starts2, ends2, **kwargs)
+
+
+def test_results_are_in_input_order_by_default():
+    idx, idx2 = chromsweep()
+    assert list(zip(idx, idx2)) == [(0, 1), (0, 3), (1, 0), (1, 2), (2, 2), (2, 4), (3, 3)]
+
+    expected = chromsweep(order="input")
+    assert np.array_equal(idx, expected[0]) and np.array_equal(idx2, expected[1])
+
+    chrs, starts, ends = QUERIES
+    chrs2, starts2, ends2 = TARGETS
+    idx, idx2, _ = ruranges.nearest_numpy(
+        chrs=chrs, starts=starts, ends=ends, chrs2=chrs2, starts2=starts2, ends2=ends2
+    )
+    assert list(idx) == sorted(idx)
+
+    _, indices = ruranges.cluster_numpy(chrs.astype(np.int64), starts, ends)
+    assert list(indices) == [0, 1, 2, 3]
+
+
+def test_bad_order_is_a_value_error():
+    chrs, starts, ends = QUERIES
+    chrs2, starts2, ends2 = TARGETS
+    with pytest.raises(ValueError):
+        chromsweep(order="sideways")
+    with pytest.raises(ValueError):
+        ruranges.nearest_numpy(
+            chrs=chrs,
+            starts=starts,
+            ends=ends,
+            chrs2=chrs2,
+            starts2=starts2,
+            ends2=ends2,
+            order="sideways",
+        )
+    with pytest.raises(ValueError):
+        ruranges.cluster_numpy(chrs.astype(np.int64), starts, ends, order="sideways")
+
+
+def test_csr_groups_the_chromsweep_hits_by_query():
+    chrs, starts, ends = QUERIES
+    chrs2, starts2, ends2 = TARGETS
+    offsets, targets = ruranges.chromsweep_csr_numpy(chrs, starts, ends, chrs2, starts2, ends2)
+    assert list(offsets) == [0, 2, 4, 6, 7]
+    assert list(targets) == [1, 3, 0, 2, 2, 4, 3]
+
+    idx, idx2 = chromsweep()
+    for i in range(len(chrs)):
+        assert list(targets[offsets[i] : offsets[i + 1]]) == sorted(idx2[idx == i])
+
+
+def test_bad_overlap_type_is_a_value_error():
+    chrs, starts, ends = QUERIES
+    chrs2, starts2, ends2 = TARGETS
+    with pytest.raises(ValueError):
+        ruranges.chromsweep_csr_numpy(
+            chrs, starts, ends, chrs2, starts2, ends2, overlap_type="most"
+        )
+    with pytest.raises(ValueError):
+        ruranges.chromsweep_numpy(
+            chrs, starts, ends, chrs2, starts2, ends2, overlap_type="most"
+        )
+
+
+def random_intervals(rng, n):
+    chrs = rng.integers(0, 3, n).astype(np.uint32)
+    starts = rng.integers(0, 200, n).astype(np.int64)
+    # Some of them empty
+    ends = starts + rng.integers(0, 20, n)
+    return chrs, starts, ends
+
+
+def test_batches_concatenate_to_the_chromsweep_hits():
+    rng = np.random.default_rng(48)
+    cases = [
+        (QUERIES, TARGETS),
+        (random_intervals(rng, 300), random_intervals(rng, 300)),
+    ]
+    for (chrs, starts, ends), (chrs2, starts2, ends2) in cases:
+        for slack in [0, 2]:
+            idx, idx2 = ruranges.chromsweep_numpy(
+                chrs, starts, ends, chrs2, starts2, ends2, slack=slack
+            )
+            expected = sorted(zip(idx, idx2))
+            for batch_size in [1, 7, 1_000_000]:
+                batches = list(
+                    ruranges.chromsweep_batches_numpy(
+                        chrs,
+                        starts,
+                        ends,
+                        chrs2,
+                        starts2,
+                        ends2,
+                        slack=slack,
+                        batch_size=batch_size,
+                    )
+                )
+                assert all(0 < len(b_idx) <= batch_size for b_idx, _ in batches)
+                idx = np.concatenate([b_idx for b_idx, _ in batches])
+                idx2 = np.concatenate([b_idx2 for _, b_idx2 in batches])
+                assert sorted(zip(idx, idx2)) == expected
+
+
+def test_batches_reject_other_overlap_types_and_relations():
+    chrs, starts, ends = QUERIES
+    chrs2, starts2, ends2 = TARGETS
+    for kwargs in [
+        dict(overlap_type="first"),
+        dict(overlap_type="most"),
+        dict(contained=True),
+        dict(overlap_relation="within"),
+    ]:
+        with pytest.raises(ValueError):
+            ruranges.chromsweep_batches_numpy(
+                chrs, starts, ends, chrs2, starts2, ends2, **kwargs
+            )
+
+
+def count_overlaps(**kwargs):
+    chrs, starts, ends = QUERIES
+    chrs2, starts2, ends2 = TARGETS
+    return ruranges.count_overlaps_numpy(
+        chrs, starts, ends, chrs2, starts2, ends2, **kwargs
+    )
+
+
+def test_out_receives_the_result():
+    chrs, starts, ends = QUERIES
+    chrs2, starts2, ends2 = TARGETS
+    expected = count_overlaps()
+    out = np.full(len(chrs), 7, dtype=np.uint32)
+    assert count_overlaps(out=out) is out
+    assert np.array_equal(out, expected)
+
+    index = ruranges.IntervalIndex(chrs2, starts2, ends2)
+    out = np.full(len(chrs), 7, dtype=np.uint32)
+    assert index.count(chrs, starts, ends, out=out) is out
+    assert np.array_equal(out, expected)
+
+    cluster_chrs = chrs.astype(np.int64)
+    for order in ["input", "genomic", "unordered"]:
+        expected = ruranges.cluster_numpy(cluster_chrs, starts, ends, order=order)
+        out = (
+            np.full(len(chrs), 7, dtype=np.int64),
+            np.full(len(chrs), 7, dtype=np.uintp),
+        )
+        cluster_ids, indices = ruranges.cluster_numpy(
+            cluster_chrs, starts, ends, order=order, out=out
+        )
+        assert cluster_ids is out[0] and indices is out[1]
+        assert np.array_equal(cluster_ids, expected[0])
+        assert np.array_equal(indices, expected[1])
+
+
+def test_coverage_per_bin():
+    chrs, starts, ends = QUERIES
+    chrom_lengths = np.array([0, 40, 12], dtype=np.int64)
+    coverage = ruranges.coverage_per_bin_numpy(chrs, starts, ends, chrom_lengths, 10)
+    assert list(coverage) == [10, 10, 0, 10, 11, 0]
+
+    out = np.full(len(coverage), 7, dtype=np.int64)
+    assert (
+        ruranges.coverage_per_bin_numpy(chrs, starts, ends, chrom_lengths, 10, out=out)
+        is out
+    )
+    assert np.array_equal(out, coverage)
+
+    with pytest.raises(ValueError):
+        ruranges.coverage_per_bin_numpy(chrs, starts, ends, chrom_lengths[:2], 10)
+    with pytest.raises(ValueError):
+        ruranges.coverage_per_bin_numpy(chrs, starts, ends, chrom_lengths, 0)
+
+
+def test_out_of_the_wrong_length_is_a_value_error():
+    chrs, starts, ends = QUERIES
+    with pytest.raises(ValueError):
+        count_overlaps(out=np.zeros(len(chrs) + 1, dtype=np.uint32))
+    with pytest.raises(ValueError):
+        ruranges.cluster_numpy(
+            chrs.astype(np.int64),
+            starts,
+            ends,
+            out=(np.zeros(len(chrs), np.int64), np.zeros(len(chrs) - 1, np.uintp)),
+        )
+
+
+def test_out_of_the_wrong_dtype_is_a_type_error():
+    chrs, _, _ = QUERIES
+    for dtype in [np.int64, np.uint64, np.float32]:
+        with pytest.raises(TypeError):
+            count_overlaps(out=np.zeros(len(chrs), dtype=dtype))
+
+
+def test_strided_arrays():
+    chrs, starts, ends = QUERIES
+    chrs2, starts2, ends2 = TARGETS
+    expected = count_overlaps()
+
+    # Strided inputs are read like contiguous ones
+    def strided(a):
+        wide = np.zeros(2 * len(a), dtype=a.dtype)
+        wide[::2] = a
+        return wide[::2]
+
+    counts = ruranges.count_overlaps_numpy(
+        strided(chrs),
+        strided(starts),
+        strided(ends),
+        strided(chrs2),
+        strided(starts2),
+        strided(ends2),
+    )
+    assert np.array_equal(counts, expected)
+
+    # but the result can not be written into a strided out
+    out = np.zeros(2 * len(chrs), dtype=np.uint32)[::2]
+    with pytest.raises(TypeError):
+        count_overlaps(out=out)
+    assert not out.any()
+
+
+def test_out_may_alias_an_input():
+    chrs, starts, ends = QUERIES
+    chrs2, starts2, ends2 = TARGETS
+    expected = count_overlaps()
+
+    # The inputs are copied before anything is written to out
+    chrs = chrs.copy()
+    counts = ruranges.count_overlaps_numpy(
+        chrs, starts, ends, chrs2, starts2, ends2, out=chrs
+    )
+    assert counts is chrs
+    assert np.array_equal(chrs, expected)
+
+    cluster_chrs = QUERIES[0].astype(np.int64)
+    expected = ruranges.cluster_numpy(cluster_chrs, starts, ends.copy())
+    aliased = ends.copy()
+    cluster_ids, _ = ruranges.cluster_numpy(
+        cluster_chrs,
+        starts,
+        aliased,
+        out=(aliased, np.zeros(len(aliased), dtype=np.uintp)),
+    )
+    assert cluster_ids is aliased
+    assert np.array_equal(aliased, expected[0])
+
+
+def nearest(**kwargs):
+    chrs, starts, ends = QUERIES
+    chrs2, starts2, ends2 = TARGETS
+    return ruranges.nearest_numpy(
+        chrs=chrs,
+        starts=starts,
+        ends=ends,
+        chrs2=chrs2,
+        starts2=starts2,
+        ends2=ends2,
+        **kwargs,
+    )
+
+
+def batches(**kwargs):
+    chrs, starts, ends = QUERIES
+    chrs2, starts2, ends2 = TARGETS
+    return list(
+        ruranges.chromsweep_batches_numpy(
+            chrs, starts, ends, chrs2, starts2, ends2, **kwargs
+        )
+    )
+
+
+def test_progress_does_not_change_the_results():
+    reports = []
+    for run in [chromsweep, count_overlaps, nearest, batches]:
+        expected = run()
+        for kwargs in [
+            dict(interruptible=True),
+            dict(progress=lambda *report: reports.append(report)),
+        ]:
+            result = run(**kwargs)
+            assert len(result) == len(expected)
+            for part, expected_part in zip(result, expected):
+                assert np.array_equal(part, expected_part)
+    assert reports
+
+
+def test_an_exception_in_the_progress_callback_cancels_the_sweep():
+    def cancel(events, chromosomes):
+        raise RuntimeError("cancelled")
+
+    for run in [chromsweep, count_overlaps, nearest]:
+        with pytest.raises(RuntimeError, match="cancelled"):
+            run(progress=cancel)
+
+
+def test_interval_index_of_mismatched_columns_is_a_value_error():
+    chrs, starts, ends = TARGETS
+    with pytest.raises(ValueError):
+        ruranges.IntervalIndex(chrs, starts[:-1], ends)